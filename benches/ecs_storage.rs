@@ -0,0 +1,188 @@
+//! Dense vs Table vs SparseSet component storage, compared with the layout before
+//! archetypes: a Vec<Option<T>> per component, scanned over every entity index
+//! Run with `cargo bench --bench ecs_storage`
+
+#![allow(dead_code)]
+
+#[path = "../src/ecs/mod.rs"]
+mod ecs;
+
+use ecs::*;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ENTITIES: usize = 100_000;
+const ITERATIONS: u32 = 20;
+
+#[derive(Clone, Copy)]
+struct Position([f64; 3]);
+
+#[derive(Clone, Copy)]
+struct Velocity([f64; 3]);
+
+#[derive(Clone, Copy)]
+struct Mass(f64);
+
+/// Rare component, every 100th entity
+#[derive(Clone, Copy)]
+struct Tag;
+
+fn create_world(storage_type: StorageType) -> World {
+    let mut world = World::new();
+    world.set_default_storage(storage_type);
+    world.register_component::<Position>();
+    world.register_component::<Velocity>();
+    world.register_component::<Mass>();
+    world.register_component::<Tag>();
+
+    for i in 0..ENTITIES {
+        let e = world.create_entity();
+        world.set_component(Position([i as f64, 0.0, 0.0]), e);
+        world.set_component(Velocity([0.0, 1.0, 0.0]), e);
+        if i % 2 == 0 {
+            world.set_component(Mass(1.0), e);
+        }
+        if i % 100 == 0 {
+            world.set_component(Tag, e);
+        }
+    }
+    world
+}
+
+/// Layout before archetypes, queries check every entity index
+struct BaselineWorld {
+    positions: Vec<Option<Position>>,
+    velocities: Vec<Option<Velocity>>,
+    masses: Vec<Option<Mass>>,
+    tags: Vec<Option<Tag>>,
+}
+
+fn create_baseline_world() -> BaselineWorld {
+    let mut world = BaselineWorld {
+        positions: Vec::new(),
+        velocities: Vec::new(),
+        masses: Vec::new(),
+        tags: Vec::new(),
+    };
+    for i in 0..ENTITIES {
+        world.positions.push(Some(Position([i as f64, 0.0, 0.0])));
+        world.velocities.push(Some(Velocity([0.0, 1.0, 0.0])));
+        world.masses.push((i % 2 == 0).then_some(Mass(1.0)));
+        world.tags.push((i % 100 == 0).then_some(Tag));
+    }
+    world
+}
+
+fn measure(name: &str, storage: &str, mut f: impl FnMut()) {
+    let mut total = Duration::ZERO;
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        f();
+        total += start.elapsed();
+    }
+    println!(
+        "{:<24} {:<9} {:>10.3} ms",
+        name,
+        storage,
+        total.as_secs_f64() * 1000.0 / ITERATIONS as f64
+    );
+}
+
+fn bench_baseline() {
+    let storage = "Baseline";
+    measure("spawn", storage, || {
+        black_box(create_baseline_world());
+    });
+
+    let mut world = create_baseline_world();
+
+    measure("iter (&mut Pos, &Vel)", storage, || {
+        for i in 0..ENTITIES {
+            if let (Some(pos), Some(vel)) = (&mut world.positions[i], &world.velocities[i]) {
+                for j in 0..3 {
+                    pos.0[j] += vel.0[j];
+                }
+            }
+        }
+    });
+
+    measure("iter (&Pos, &Mass)", storage, || {
+        let mut sum = 0.0;
+        for i in 0..ENTITIES {
+            if let (Some(pos), Some(mass)) = (&world.positions[i], &world.masses[i]) {
+                sum += pos.0[0] * mass.0;
+            }
+        }
+        black_box(sum);
+    });
+
+    measure("iter &Tag", storage, || {
+        black_box((0..ENTITIES).filter(|&i| world.tags[i].is_some()).count());
+    });
+
+    measure("add/remove Mass", storage, || {
+        for i in 0..ENTITIES {
+            world.masses[i] = None;
+            world.masses[i] = Some(Mass(2.0));
+        }
+    });
+}
+
+fn bench_storage(storage_type: StorageType) {
+    let storage = &format!("{:?}", storage_type);
+    measure("spawn", storage, || {
+        black_box(create_world(storage_type));
+    });
+
+    let world = create_world(storage_type);
+
+    measure("iter (&mut Pos, &Vel)", storage, || {
+        let mut query = world.query::<(&mut Position, &Velocity)>();
+        for item in query.iter_mut() {
+            let (mut pos, vel) = item.comp;
+            for i in 0..3 {
+                pos.0[i] += vel.0[i];
+            }
+        }
+    });
+
+    measure("par (&mut Pos, &Vel)", storage, || {
+        let mut query = world.query::<(&mut Position, &Velocity)>();
+        query.par_for_each_mut(|item| {
+            let (mut pos, vel) = item.comp;
+            for i in 0..3 {
+                pos.0[i] += vel.0[i];
+            }
+        });
+    });
+
+    measure("iter (&Pos, &Mass)", storage, || {
+        let query = world.query::<(&Position, &Mass)>();
+        let mut sum = 0.0;
+        for item in query.iter() {
+            sum += item.comp.0 .0[0] * item.comp.1 .0;
+        }
+        black_box(sum);
+    });
+
+    measure("iter &Tag", storage, || {
+        let query = world.query::<&Tag>();
+        black_box(query.iter().count());
+    });
+
+    let mut world = create_world(storage_type);
+    measure("add/remove Mass", storage, || {
+        for i in 0..world.get_entities_capacity() {
+            let e = world.get_entity(i).unwrap();
+            world.remove_component::<Mass>(e);
+            world.set_component(Mass(2.0), e);
+        }
+    });
+}
+
+fn main() {
+    bench_baseline();
+    bench_storage(StorageType::Dense);
+    bench_storage(StorageType::Table);
+    bench_storage(StorageType::SparseSet);
+}
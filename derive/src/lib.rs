@@ -0,0 +1,63 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Index};
+
+/// Derives Bundle for a struct whose fields are components:
+/// #[derive(Bundle)]
+/// struct PhysBundle { position: Position, mass: Mass }
+/// The generated impl refers to crate::ecs::Bundle, World, Entity, ArchetypeId and
+/// EcsError, so it only compiles inside the engine crate, where ecs is a top-level module
+#[proc_macro_derive(Bundle)]
+pub fn derive_bundle(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields
+                .named
+                .iter()
+                .map(|field| {
+                    let ident = &field.ident;
+                    quote! { #ident }
+                })
+                .collect(),
+            Fields::Unnamed(fields) => (0..fields.unnamed.len())
+                .map(|index| {
+                    let index = Index::from(index);
+                    quote! { #index }
+                })
+                .collect(),
+            Fields::Unit => Vec::new(),
+        },
+        _ => {
+            return syn::Error::new_spanned(name, "Bundle can only be derived for structs")
+                .to_compile_error()
+                .into()
+        }
+    };
+
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    quote! {
+        impl #impl_generics crate::ecs::Bundle for #name #type_generics #where_clause {
+            fn get_type_ids(
+                &self,
+                world: &mut crate::ecs::World,
+                type_ids: &mut Vec<std::any::TypeId>,
+            ) -> Result<(), crate::ecs::EcsError> {
+                #(world.add_bundle_type_id(&self.#fields, type_ids)?;)*
+                Ok(())
+            }
+
+            fn write_components(
+                self,
+                world: &mut crate::ecs::World,
+                entity: crate::ecs::Entity,
+                previous: crate::ecs::ArchetypeId,
+            ) {
+                #(world.write_bundle_component(self.#fields, entity, previous);)*
+            }
+        }
+    }
+    .into()
+}
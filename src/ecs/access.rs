@@ -0,0 +1,186 @@
+use crate::ecs::{Component, Resource};
+use std::any::{type_name, TypeId};
+use std::fmt;
+
+/// AccessKind
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// AccessTarget
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+pub enum AccessTarget {
+    Component(TypeId),
+    Resource(TypeId),
+    /// Data owned by a single function, like its command buffer. Only parameters
+    /// of the same function can conflict on it
+    Private(TypeId),
+    /// The whole world, reported as the conflict of exclusive access
+    World,
+}
+
+/// AccessItem
+#[derive(Copy, Clone, Debug)]
+pub struct AccessItem {
+    pub target: AccessTarget,
+    pub name: &'static str,
+    pub kind: AccessKind,
+}
+
+/// Access
+/// Components and resources a query or a logic function reads and writes.
+/// Exclusive access conflicts with everything
+#[derive(Clone, Debug, Default)]
+pub struct Access {
+    items: Vec<AccessItem>,
+    exclusive: bool,
+}
+
+impl Access {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn exclusive() -> Self {
+        Self {
+            items: Vec::new(),
+            exclusive: true,
+        }
+    }
+
+    pub fn is_exclusive(&self) -> bool {
+        self.exclusive
+    }
+
+    pub fn get_items(&self) -> &[AccessItem] {
+        &self.items
+    }
+
+    pub fn add_component_read<T: Component>(&mut self) {
+        self.add(
+            AccessTarget::Component(T::get_type_id()),
+            type_name::<T>(),
+            AccessKind::Read,
+        );
+    }
+
+    pub fn add_component_write<T: Component>(&mut self) {
+        self.add(
+            AccessTarget::Component(T::get_type_id()),
+            type_name::<T>(),
+            AccessKind::Write,
+        );
+    }
+
+    pub fn add_resource_read<R: Resource>(&mut self) {
+        self.add(
+            AccessTarget::Resource(R::get_type_id()),
+            type_name::<R>(),
+            AccessKind::Read,
+        );
+    }
+
+    pub fn add_resource_write<R: Resource>(&mut self) {
+        self.add(
+            AccessTarget::Resource(R::get_type_id()),
+            type_name::<R>(),
+            AccessKind::Write,
+        );
+    }
+
+    pub fn add_private_write<P: 'static>(&mut self) {
+        self.add(
+            AccessTarget::Private(TypeId::of::<P>()),
+            type_name::<P>(),
+            AccessKind::Write,
+        );
+    }
+
+    pub fn set_exclusive(&mut self) {
+        self.exclusive = true;
+    }
+
+    pub fn extend(&mut self, other: &Access) {
+        self.exclusive |= other.exclusive;
+        for item in &other.items {
+            self.add(item.target, item.name, item.kind);
+        }
+    }
+
+    /// Accesses of two functions are compatible if they only share reads
+    pub fn is_compatible(&self, other: &Access) -> bool {
+        !self.exclusive
+            && !other.exclusive
+            && self
+                .get_conflicts(other)
+                .iter()
+                .all(|item| matches!(item.target, AccessTarget::Private(_)))
+    }
+
+    /// Targets written by one access and used by the other. Exclusive access
+    /// conflicts on AccessTarget::World with any component or resource of the other
+    pub fn get_conflicts(&self, other: &Access) -> Vec<AccessItem> {
+        if (self.exclusive && other.has_shared_items())
+            || (other.exclusive && self.has_shared_items())
+        {
+            return vec![AccessItem {
+                target: AccessTarget::World,
+                name: "exclusive access",
+                kind: AccessKind::Write,
+            }];
+        }
+        self.items
+            .iter()
+            .filter(|item| {
+                other.items.iter().any(|other_item| {
+                    item.target == other_item.target
+                        && (item.kind == AccessKind::Write || other_item.kind == AccessKind::Write)
+                })
+            })
+            .copied()
+            .collect()
+    }
+
+    /// Items other functions can access too
+    fn has_shared_items(&self) -> bool {
+        self.items
+            .iter()
+            .any(|item| !matches!(item.target, AccessTarget::Private(_)))
+    }
+
+    /// Write wins over read of the same target
+    fn add(&mut self, target: AccessTarget, name: &'static str, kind: AccessKind) {
+        match self.items.iter_mut().find(|item| item.target == target) {
+            Some(item) => {
+                if kind == AccessKind::Write {
+                    item.kind = AccessKind::Write;
+                }
+            }
+            None => self.items.push(AccessItem { target, name, kind }),
+        }
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.exclusive {
+            return write!(f, "exclusive");
+        }
+        let names = |kind: AccessKind| {
+            self.items
+                .iter()
+                .filter(|item| item.kind == kind)
+                .map(|item| item.name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        write!(
+            f,
+            "reads [{}] writes [{}]",
+            names(AccessKind::Read),
+            names(AccessKind::Write)
+        )
+    }
+}
@@ -0,0 +1,165 @@
+use crate::ecs::Entity;
+use std::any::TypeId;
+use std::collections::HashMap;
+
+#[derive(Eq, Hash, PartialEq, Copy, Clone, Debug)]
+pub struct ArchetypeId(usize);
+
+impl ArchetypeId {
+    /// Archetype of entities without components
+    pub const EMPTY: ArchetypeId = ArchetypeId(0);
+
+    pub fn to_num(self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct EntityLocation {
+    pub archetype: ArchetypeId,
+    pub row: usize,
+}
+
+/// Archetype
+/// All entities with the same set of components. Row of an entity in the archetype
+/// is the row of its components in every Table storage column of the archetype
+pub struct Archetype {
+    id: ArchetypeId,
+    types: Vec<TypeId>,
+    entities: Vec<Entity>,
+    add_edges: HashMap<TypeId, ArchetypeId>,
+    remove_edges: HashMap<TypeId, ArchetypeId>,
+}
+
+impl Archetype {
+    fn new(id: ArchetypeId, types: Vec<TypeId>) -> Self {
+        Self {
+            id,
+            types,
+            entities: Vec::new(),
+            add_edges: HashMap::new(),
+            remove_edges: HashMap::new(),
+        }
+    }
+
+    pub fn get_id(&self) -> ArchetypeId {
+        self.id
+    }
+
+    pub fn get_types(&self) -> &[TypeId] {
+        &self.types
+    }
+
+    pub fn contains(&self, type_id: TypeId) -> bool {
+        self.types.binary_search(&type_id).is_ok()
+    }
+
+    pub fn get_entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub(in crate::ecs) fn push_entity(&mut self, entity: Entity) -> usize {
+        self.entities.push(entity);
+        self.entities.len() - 1
+    }
+
+    /// Returns the entity which was moved to the row, if any
+    pub(in crate::ecs) fn swap_remove_entity(&mut self, row: usize) -> Option<Entity> {
+        self.entities.swap_remove(row);
+        self.entities.get(row).copied()
+    }
+}
+
+/// Archetypes
+pub struct Archetypes {
+    archetypes: Vec<Archetype>,
+    by_types: HashMap<Vec<TypeId>, ArchetypeId>,
+}
+
+impl Archetypes {
+    pub(in crate::ecs) fn new() -> Self {
+        let mut archetypes = Self {
+            archetypes: Vec::new(),
+            by_types: HashMap::new(),
+        };
+        let empty = archetypes.get_or_create(Vec::new());
+        debug_assert_eq!(empty, ArchetypeId::EMPTY);
+        archetypes
+    }
+
+    pub fn get(&self, id: ArchetypeId) -> &Archetype {
+        &self.archetypes[id.0]
+    }
+
+    pub(in crate::ecs) fn get_mut(&mut self, id: ArchetypeId) -> &mut Archetype {
+        &mut self.archetypes[id.0]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Archetype> {
+        self.archetypes.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.archetypes.len()
+    }
+
+    pub(in crate::ecs) fn get_with_added(&mut self, id: ArchetypeId, type_id: TypeId) -> ArchetypeId {
+        if let Some(&target) = self.archetypes[id.0].add_edges.get(&type_id) {
+            return target;
+        }
+        let mut types = self.archetypes[id.0].types.clone();
+        if let Err(pos) = types.binary_search(&type_id) {
+            types.insert(pos, type_id);
+        }
+        let target = self.get_or_create(types);
+        self.archetypes[id.0].add_edges.insert(type_id, target);
+        self.archetypes[target.0].remove_edges.insert(type_id, id);
+        target
+    }
+
+    pub(in crate::ecs) fn get_with_removed(&mut self, id: ArchetypeId, type_id: TypeId) -> ArchetypeId {
+        if let Some(&target) = self.archetypes[id.0].remove_edges.get(&type_id) {
+            return target;
+        }
+        let mut types = self.archetypes[id.0].types.clone();
+        if let Ok(pos) = types.binary_search(&type_id) {
+            types.remove(pos);
+        }
+        let target = self.get_or_create(types);
+        self.archetypes[id.0].remove_edges.insert(type_id, target);
+        self.archetypes[target.0].add_edges.insert(type_id, id);
+        target
+    }
+
+    /// Archetype with all the types added at once, without the archetypes in between
+    pub(in crate::ecs) fn get_with_added_all(&mut self, id: ArchetypeId, type_ids: &[TypeId]) -> ArchetypeId {
+        let mut types = self.archetypes[id.0].types.clone();
+        for type_id in type_ids {
+            if let Err(pos) = types.binary_search(type_id) {
+                types.insert(pos, *type_id);
+            }
+        }
+        if types.len() == self.archetypes[id.0].types.len() {
+            return id;
+        }
+        self.get_or_create(types)
+    }
+
+    fn get_or_create(&mut self, types: Vec<TypeId>) -> ArchetypeId {
+        if let Some(&id) = self.by_types.get(&types) {
+            return id;
+        }
+        let id = ArchetypeId(self.archetypes.len());
+        self.by_types.insert(types.clone(), id);
+        self.archetypes.push(Archetype::new(id, types));
+        id
+    }
+}
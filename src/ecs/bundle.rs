@@ -0,0 +1,37 @@
+use crate::ecs::{ArchetypeId, Component, EcsError, Entity, World};
+use std::any::TypeId;
+
+/// Bundle
+/// Set of components inserted together. The entity is moved to the archetype
+/// with all of them at once. Implemented for tuples of components,
+/// derived with #[derive(Bundle)] for structs
+pub trait Bundle: Send + 'static {
+    /// Registers the components and appends their type ids
+    fn get_type_ids(&self, world: &mut World, type_ids: &mut Vec<TypeId>) -> Result<(), EcsError>;
+
+    /// Called once the entity is in the archetype with all the components,
+    /// previous is its archetype before the insertion
+    fn write_components(self, world: &mut World, entity: Entity, previous: ArchetypeId);
+}
+
+macro_rules! impl_bundle_for_tuple {
+    ($($type_name: ident, $var_name: ident, $num: tt),*) => {
+        impl<$($type_name : Component, )*> Bundle for ($($type_name, )*) {
+            fn get_type_ids(&self, world: &mut World, type_ids: &mut Vec<TypeId>) -> Result<(), EcsError> {
+                $(
+                    world.add_bundle_type_id(&self.$num, type_ids)?;
+                )*
+                Ok(())
+            }
+
+            fn write_components(self, world: &mut World, entity: Entity, previous: ArchetypeId) {
+                let ($($var_name, )*) = self;
+                $(
+                    world.write_bundle_component($var_name, entity, previous);
+                )*
+            }
+        }
+    };
+}
+
+all_tuples!(impl_bundle_for_tuple);
@@ -0,0 +1,83 @@
+use std::ops::{Deref, DerefMut};
+
+/// Tick is newer if it happened after the last run of the system. Works with wrapped ticks
+/// as long as both ticks are less than u32::MAX ticks old
+pub fn is_tick_newer(tick: u32, last_run: u32, this_run: u32) -> bool {
+    let ticks_since_insert = this_run.wrapping_sub(tick);
+    let ticks_since_system = this_run.wrapping_sub(last_run);
+    ticks_since_system > ticks_since_insert
+}
+
+/// ComponentTicks
+#[derive(Copy, Clone, Debug)]
+pub struct ComponentTicks {
+    pub added: u32,
+    pub changed: u32,
+}
+
+impl ComponentTicks {
+    pub fn new(tick: u32) -> Self {
+        Self {
+            added: tick,
+            changed: tick,
+        }
+    }
+
+    pub fn is_added(&self, ticks: SystemTicks) -> bool {
+        is_tick_newer(self.added, ticks.last_run, ticks.this_run)
+    }
+
+    pub fn is_changed(&self, ticks: SystemTicks) -> bool {
+        is_tick_newer(self.changed, ticks.last_run, ticks.this_run)
+    }
+}
+
+/// SystemTicks
+/// last_run is the tick of the previous run of the function, this_run is the current tick
+#[derive(Copy, Clone, Debug)]
+pub struct SystemTicks {
+    pub last_run: u32,
+    pub this_run: u32,
+}
+
+/// Mut
+/// Mutable access to a component which marks it as changed on write
+pub struct Mut<'w, T> {
+    value: &'w mut T,
+    ticks: &'w mut ComponentTicks,
+    change_tick: u32,
+}
+
+impl<'w, T> Mut<'w, T> {
+    pub fn new(value: &'w mut T, ticks: &'w mut ComponentTicks, change_tick: u32) -> Self {
+        Self {
+            value,
+            ticks,
+            change_tick,
+        }
+    }
+
+    pub fn get_ticks(&self) -> ComponentTicks {
+        *self.ticks
+    }
+
+    /// Mutable access without marking the component as changed
+    pub fn bypass_change_detection(&mut self) -> &mut T {
+        self.value
+    }
+}
+
+impl<T> Deref for Mut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+impl<T> DerefMut for Mut<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.ticks.changed = self.change_tick;
+        self.value
+    }
+}
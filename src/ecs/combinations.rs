@@ -0,0 +1,131 @@
+use crate::ecs::{
+    Entity, EntityLocation, FetchResult, Fetcherable, Query, QueryIterItem, QueryIterMutItem,
+};
+use std::marker::PhantomData;
+
+/// Combinations
+/// Walks index combinations i0 < i1 < ... < iK-1 of the matched entities,
+/// so every unordered set of K entities is visited once
+struct Combinations<const K: usize> {
+    entities: Vec<(Entity, EntityLocation)>,
+    indices: [usize; K],
+    started: bool,
+}
+
+impl<const K: usize> Combinations<K> {
+    fn new(entities: Vec<(Entity, EntityLocation)>) -> Self {
+        Self {
+            entities,
+            indices: [0; K],
+            started: false,
+        }
+    }
+
+    fn advance(&mut self) -> bool {
+        let n = self.entities.len();
+        if K == 0 || K > n {
+            return false;
+        }
+
+        if !self.started {
+            self.started = true;
+            for (i, index) in self.indices.iter_mut().enumerate() {
+                *index = i;
+            }
+            return true;
+        }
+
+        let Some(i) = (0..K).rev().find(|&i| self.indices[i] < n - K + i) else {
+            self.indices = [n; K];
+            return false;
+        };
+        self.indices[i] += 1;
+        for j in i + 1..K {
+            self.indices[j] = self.indices[j - 1] + 1;
+        }
+        true
+    }
+
+    fn get(&self, i: usize) -> (Entity, EntityLocation) {
+        self.entities[self.indices[i]]
+    }
+
+    fn is_finished(&self) -> bool {
+        self.started
+            && self
+                .indices
+                .iter()
+                .any(|&index| index >= self.entities.len())
+    }
+}
+
+/// QueryCombinationIter
+pub struct QueryCombinationIter<'q, 'w: 'q, T: Fetcherable, const K: usize> {
+    query: &'q Query<'w, T>,
+    combinations: Combinations<K>,
+}
+
+impl<'q, 'w: 'q, T: Fetcherable, const K: usize> QueryCombinationIter<'q, 'w, T, K> {
+    pub fn new(query: &'q Query<'w, T>) -> Self {
+        Self {
+            query,
+            combinations: Combinations::new(query.get_matched_entities()),
+        }
+    }
+}
+
+impl<'q, 'w: 'q, T: Fetcherable, const K: usize> Iterator for QueryCombinationIter<'q, 'w, T, K> {
+    type Item = [QueryIterItem<'q, T>; K];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.combinations.is_finished() || !self.combinations.advance() {
+            return None;
+        }
+        let items: [_; K] = std::array::from_fn(|i| {
+            let (entity, location) = self.combinations.get(i);
+            match T::fetch_entity(&self.query.fetch, entity, location) {
+                FetchResult::Some(comp) => Some(QueryIterItem { ent: entity, comp }),
+                FetchResult::None => None,
+            }
+        });
+        Some(items.map(|item| item.expect("Matched entity is not fetched")))
+    }
+}
+
+/// QueryCombinationIterMut
+/// Not an Iterator: items borrow the iterator, so mutable access to an entity
+/// can't outlive the combination it was fetched for
+pub struct QueryCombinationIterMut<'q, 'w: 'q, T: Fetcherable, const K: usize> {
+    query: &'q Query<'w, T>,
+    combinations: Combinations<K>,
+    marker: PhantomData<&'q mut Query<'w, T>>,
+}
+
+impl<'q, 'w: 'q, T: Fetcherable, const K: usize> QueryCombinationIterMut<'q, 'w, T, K> {
+    pub fn new(query: &'q mut Query<'w, T>) -> Self {
+        let combinations = Combinations::new(query.get_matched_entities());
+        Self {
+            query,
+            combinations,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn fetch_next(&mut self) -> Option<[QueryIterMutItem<'_, T>; K]> {
+        if self.combinations.is_finished() || !self.combinations.advance() {
+            return None;
+        }
+        let fetch = &self.query.fetch;
+        let combinations = &self.combinations;
+        let items: [_; K] = std::array::from_fn(|i| {
+            let (entity, location) = combinations.get(i);
+            // SAFETY: Indices of a combination are distinct, so are the fetched entities.
+            // Items borrow the iterator, which borrows the query mutably
+            match unsafe { T::fetch_entity_mut(fetch, entity, location) } {
+                FetchResult::Some(comp) => Some(QueryIterMutItem { ent: entity, comp }),
+                FetchResult::None => None,
+            }
+        });
+        Some(items.map(|item| item.expect("Matched entity is not fetched")))
+    }
+}
@@ -1,11 +1,24 @@
 use std::any::TypeId;
 
-pub trait Component: 'static {
+/// Component
+/// Send + Sync so that queries can be iterated in parallel
+pub trait Component: Send + Sync + 'static {
     fn get_type_id() -> TypeId;
 }
 
-impl<T: 'static> Component for T {
+impl<T: Send + Sync + 'static> Component for T {
     fn get_type_id() -> TypeId {
         TypeId::of::<T>()
     }
 }
+
+/// StorageType
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum StorageType {
+    /// Option<T> slot for every entity
+    Dense,
+    /// Contiguous column per archetype
+    Table,
+    /// Packed array with sparse entity index, for components present on few entities
+    SparseSet,
+}
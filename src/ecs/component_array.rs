@@ -1,36 +1,242 @@
-use crate::ecs::{Component, Entity};
+use crate::ecs::{
+    ArchetypeId, Component, ComponentTicks, Entity, EntityLocation, Mut, SparseSet, StorageType,
+};
 use std::any::Any;
-use std::cell::{Cell, UnsafeCell};
+use std::cell::UnsafeCell;
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
 use std::ptr::NonNull;
+use std::sync::atomic::{AtomicIsize, Ordering};
 
 /// ComponentArray
-pub(in crate::ecs) trait ComponentArray {
+pub(in crate::ecs) trait ComponentArray: Send + Sync {
     fn push_none(&mut self);
+    fn clear_entity(&mut self, entity: Entity, location: EntityLocation);
+    fn move_entity(&mut self, from: EntityLocation, to: ArchetypeId);
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
 }
 
+/// ComponentSlot
+pub struct ComponentSlot<T> {
+    pub value: T,
+    pub ticks: ComponentTicks,
+}
+
+/// ComponentStorage
+pub enum ComponentStorage<T: Component> {
+    Dense(Vec<Option<ComponentSlot<T>>>),
+    /// Column per archetype id, indexed by the row of the entity in the archetype
+    Table(Vec<Vec<ComponentSlot<T>>>),
+    SparseSet(SparseSet<ComponentSlot<T>>),
+}
+
+impl<T: Component> ComponentStorage<T> {
+    fn new(storage_type: StorageType) -> Self {
+        match storage_type {
+            StorageType::Dense => Self::Dense(Vec::new()),
+            StorageType::Table => Self::Table(Vec::new()),
+            StorageType::SparseSet => Self::SparseSet(SparseSet::new()),
+        }
+    }
+
+    pub fn get_storage_type(&self) -> StorageType {
+        match self {
+            Self::Dense(_) => StorageType::Dense,
+            Self::Table(_) => StorageType::Table,
+            Self::SparseSet(_) => StorageType::SparseSet,
+        }
+    }
+
+    pub fn get(&self, entity: Entity, location: EntityLocation) -> Option<&T> {
+        self.get_slot(entity, location).map(|slot| &slot.value)
+    }
+
+    /// Mutable access without marking the component as changed
+    pub fn get_mut(&mut self, entity: Entity, location: EntityLocation) -> Option<&mut T> {
+        self.get_slot_mut(entity, location)
+            .map(|slot| &mut slot.value)
+    }
+
+    pub fn get_tracked_mut(
+        &mut self,
+        entity: Entity,
+        location: EntityLocation,
+        change_tick: u32,
+    ) -> Option<Mut<'_, T>> {
+        let ComponentSlot { value, ticks } = self.get_slot_mut(entity, location)?;
+        Some(Mut::new(value, ticks, change_tick))
+    }
+
+    pub fn get_ticks(&self, entity: Entity, location: EntityLocation) -> Option<ComponentTicks> {
+        self.get_slot(entity, location).map(|slot| slot.ticks)
+    }
+
+    fn get_slot(&self, entity: Entity, location: EntityLocation) -> Option<&ComponentSlot<T>> {
+        match self {
+            Self::Dense(components) => components.get(entity.to_num())?.as_ref(),
+            Self::Table(columns) => columns.get(location.archetype.to_num())?.get(location.row),
+            Self::SparseSet(set) => set.get(entity),
+        }
+    }
+
+    fn get_slot_mut(
+        &mut self,
+        entity: Entity,
+        location: EntityLocation,
+    ) -> Option<&mut ComponentSlot<T>> {
+        match self {
+            Self::Dense(components) => components.get_mut(entity.to_num())?.as_mut(),
+            Self::Table(columns) => columns
+                .get_mut(location.archetype.to_num())?
+                .get_mut(location.row),
+            Self::SparseSet(set) => set.get_mut(entity),
+        }
+    }
+
+    /// Points into the heap buffers of the storage, so it's got through a shared reference
+    /// and no &mut of the whole storage is created. Slots of different entities can be
+    /// accessed through such pointers from different threads at the same time
+    fn get_slot_ptr(
+        &self,
+        entity: Entity,
+        location: EntityLocation,
+    ) -> Option<*mut ComponentSlot<T>> {
+        match self {
+            Self::Dense(components) => {
+                let index = entity.to_num();
+                if components.get(index)?.is_none() {
+                    return None;
+                }
+                let slot =
+                    unsafe { components.as_ptr().add(index) as *mut Option<ComponentSlot<T>> };
+                // SAFETY: The slot is Some, checked above
+                unsafe { (*slot).as_mut().map(|slot| slot as *mut _) }
+            }
+            Self::Table(columns) => {
+                let column = columns.get(location.archetype.to_num())?;
+                if location.row >= column.len() {
+                    return None;
+                }
+                Some(unsafe { column.as_ptr().add(location.row) as *mut ComponentSlot<T> })
+            }
+            Self::SparseSet(set) => set.get_ptr(entity),
+        }
+    }
+
+    /// Location must be the new location of the entity with all other components already moved
+    fn insert(&mut self, component: T, entity: Entity, location: EntityLocation, tick: u32) {
+        let slot = ComponentSlot {
+            value: component,
+            ticks: ComponentTicks::new(tick),
+        };
+        match self {
+            Self::Dense(components) => components[entity.to_num()] = Some(slot),
+            Self::Table(columns) => {
+                let column = Self::column_mut(columns, location.archetype);
+                debug_assert_eq!(column.len(), location.row);
+                column.push(slot);
+            }
+            Self::SparseSet(set) => set.insert(entity, slot),
+        }
+    }
+
+    fn remove(&mut self, entity: Entity, location: EntityLocation) -> Option<T> {
+        let slot = match self {
+            Self::Dense(components) => components[entity.to_num()].take(),
+            Self::Table(columns) => {
+                let column = columns.get_mut(location.archetype.to_num())?;
+                Some(column.swap_remove(location.row))
+            }
+            Self::SparseSet(set) => set.remove(entity),
+        };
+        slot.map(|slot| slot.value)
+    }
+
+    fn column_mut(
+        columns: &mut Vec<Vec<ComponentSlot<T>>>,
+        archetype: ArchetypeId,
+    ) -> &mut Vec<ComponentSlot<T>> {
+        let index = archetype.to_num();
+        if columns.len() <= index {
+            columns.resize_with(index + 1, Vec::new);
+        }
+        &mut columns[index]
+    }
+}
+
 /// ComponentArrayCell
 pub struct CACell<T: Component> {
-    borrow: Cell<BorrowFlag>,
-    components: UnsafeCell<Vec<Option<T>>>,
+    borrow: AtomicIsize,
+    components: UnsafeCell<ComponentStorage<T>>,
 }
 
+// SAFETY: Access to the storage is guarded by the atomic borrow flag
+unsafe impl<T: Component> Sync for CACell<T> {}
+
 impl<T: Component> CACell<T> {
-    pub fn set_component(&mut self, component: T, e: Entity) {
-        self.components.get_mut()[e.to_num()] = Some(component).into();
+    pub(in crate::ecs) fn set_component(
+        &mut self,
+        component: T,
+        e: Entity,
+        location: EntityLocation,
+        tick: u32,
+    ) {
+        self.components
+            .get_mut()
+            .insert(component, e, location, tick);
+    }
+
+    pub(in crate::ecs) fn replace_component(
+        &mut self,
+        component: T,
+        e: Entity,
+        location: EntityLocation,
+        tick: u32,
+    ) {
+        let mut slot = self
+            .components
+            .get_mut()
+            .get_tracked_mut(e, location, tick)
+            .expect("No such component");
+        *slot = component;
     }
 
-    pub fn remove_component(&mut self, entity: Entity) {
-        self.components.get_mut()[entity.to_num()] = None.into();
+    pub(in crate::ecs) fn remove_component(
+        &mut self,
+        entity: Entity,
+        location: EntityLocation,
+    ) -> Option<T> {
+        self.components.get_mut().remove(entity, location)
+    }
+
+    /// Reads ticks without borrowing, so it can be used while the array is mutably borrowed
+    /// SAFETY: Component of the entity must not be mutably accessed during the call
+    pub(in crate::ecs) unsafe fn get_ticks_unchecked(
+        &self,
+        entity: Entity,
+        location: EntityLocation,
+    ) -> Option<ComponentTicks> {
+        unsafe { (*self.components.get()).get_ticks(entity, location) }
     }
 }
 
 impl<T: Component> ComponentArray for CACell<T> {
     fn push_none(&mut self) {
-        self.components.get_mut().push(None);
+        if let ComponentStorage::Dense(components) = self.components.get_mut() {
+            components.push(None);
+        }
+    }
+
+    fn clear_entity(&mut self, entity: Entity, location: EntityLocation) {
+        self.remove_component(entity, location);
+    }
+
+    fn move_entity(&mut self, from: EntityLocation, to: ArchetypeId) {
+        if let ComponentStorage::Table(columns) = self.components.get_mut() {
+            let component = columns[from.archetype.to_num()].swap_remove(from.row);
+            ComponentStorage::column_mut(columns, to).push(component);
+        }
     }
 
     fn as_any(&self) -> &dyn Any {
@@ -43,8 +249,9 @@ impl<T: Component> ComponentArray for CACell<T> {
 }
 
 /// BorrowFlag
-type BorrowFlag = isize;
-const UNUSED: BorrowFlag = 0;
+/// Kept in an AtomicIsize, so arrays can be borrowed from several threads
+pub(in crate::ecs) type BorrowFlag = isize;
+pub(in crate::ecs) const UNUSED: BorrowFlag = 0;
 fn is_writing(x: BorrowFlag) -> bool {
     x < UNUSED
 }
@@ -53,19 +260,23 @@ fn is_reading(x: BorrowFlag) -> bool {
 }
 
 /// CABorrow
-struct CABorrow<'b> {
-    borrow: &'b Cell<BorrowFlag>,
+pub(in crate::ecs) struct CABorrow<'b> {
+    borrow: &'b AtomicIsize,
 }
 
 impl<'b> CABorrow<'b> {
     #[inline]
-    fn new(borrow: &'b Cell<BorrowFlag>) -> Option<CABorrow<'b>> {
-        let b = borrow.get().wrapping_add(1);
-        if !is_reading(b) {
-            None
-        } else {
-            borrow.set(b);
-            Some(CABorrow { borrow })
+    pub(in crate::ecs) fn new(borrow: &'b AtomicIsize) -> Option<CABorrow<'b>> {
+        let mut current = borrow.load(Ordering::Relaxed);
+        loop {
+            let b = current.wrapping_add(1);
+            if !is_reading(b) {
+                return None;
+            }
+            match borrow.compare_exchange_weak(current, b, Ordering::Acquire, Ordering::Relaxed) {
+                Ok(_) => return Some(CABorrow { borrow }),
+                Err(actual) => current = actual,
+            }
         }
     }
 }
@@ -73,19 +284,17 @@ impl<'b> CABorrow<'b> {
 impl Drop for CABorrow<'_> {
     #[inline]
     fn drop(&mut self) {
-        let borrow = self.borrow.get();
+        let borrow = self.borrow.fetch_sub(1, Ordering::Release);
         debug_assert!(is_reading(borrow));
-        self.borrow.set(borrow - 1);
     }
 }
 
 impl Clone for CABorrow<'_> {
     #[inline]
     fn clone(&self) -> Self {
-        let borrow = self.borrow.get();
+        let borrow = self.borrow.fetch_add(1, Ordering::Relaxed);
         debug_assert!(is_reading(borrow));
         assert!(borrow != isize::MAX);
-        self.borrow.set(borrow + 1);
         CABorrow {
             borrow: self.borrow,
         }
@@ -93,37 +302,32 @@ impl Clone for CABorrow<'_> {
 }
 
 /// CABorrowMut
-struct CABorrowMut<'b> {
-    borrow: &'b Cell<BorrowFlag>,
+pub(in crate::ecs) struct CABorrowMut<'b> {
+    borrow: &'b AtomicIsize,
 }
 
 impl Drop for CABorrowMut<'_> {
     #[inline]
     fn drop(&mut self) {
-        let borrow = self.borrow.get();
+        let borrow = self.borrow.fetch_add(1, Ordering::Release);
         debug_assert!(is_writing(borrow));
-        self.borrow.set(borrow + 1);
     }
 }
 
 impl<'b> CABorrowMut<'b> {
     #[inline]
-    fn new(borrow: &'b Cell<BorrowFlag>) -> Option<CABorrowMut<'b>> {
-        match borrow.get() {
-            UNUSED => {
-                borrow.set(UNUSED - 1);
-                Some(CABorrowMut { borrow })
-            }
-            _ => None,
-        }
+    pub(in crate::ecs) fn new(borrow: &'b AtomicIsize) -> Option<CABorrowMut<'b>> {
+        borrow
+            .compare_exchange(UNUSED, UNUSED - 1, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| CABorrowMut { borrow })
     }
 
     #[inline]
     fn clone(&self) -> CABorrowMut<'b> {
-        let borrow = self.borrow.get();
+        let borrow = self.borrow.fetch_sub(1, Ordering::Relaxed);
         debug_assert!(is_writing(borrow));
         assert!(borrow != isize::MIN);
-        self.borrow.set(borrow - 1);
         CABorrowMut {
             borrow: self.borrow,
         }
@@ -132,12 +336,16 @@ impl<'b> CABorrowMut<'b> {
 
 /// CARef
 pub struct CARef<'b, T: Component + 'b> {
-    value: NonNull<Vec<Option<T>>>,
+    value: NonNull<ComponentStorage<T>>,
     borrow: CABorrow<'b>,
 }
 
+// SAFETY: Shared borrow of the storage, same as &ComponentStorage<T>
+unsafe impl<T: Component> Send for CARef<'_, T> {}
+unsafe impl<T: Component> Sync for CARef<'_, T> {}
+
 impl<T: Component> Deref for CARef<'_, T> {
-    type Target = Vec<Option<T>>;
+    type Target = ComponentStorage<T>;
 
     fn deref(&self) -> &Self::Target {
         unsafe { self.value.as_ref() }
@@ -146,19 +354,34 @@ impl<T: Component> Deref for CARef<'_, T> {
 
 /// CARefMut
 pub struct CARefMut<'b, T: Component + 'b> {
-    value: NonNull<Vec<Option<T>>>,
+    value: NonNull<ComponentStorage<T>>,
     borrow: CABorrowMut<'b>,
     marker: PhantomData<&'b mut T>,
 }
 
+// SAFETY: Parallel queries share the borrow between threads, every thread
+// accesses components of its own entities only
+unsafe impl<T: Component> Send for CARefMut<'_, T> {}
+unsafe impl<T: Component> Sync for CARefMut<'_, T> {}
+
 impl<'b, T: Component + 'b> CARefMut<'b, T> {
-    pub unsafe fn deref_mut_unsafe(&self) -> &mut Vec<Option<T>> {
-        unsafe { NonNull::new_unchecked(self.value.as_ptr()).as_mut() }
+    /// Mutable access to a single component through a shared reference, used by
+    /// parallel queries. Only the slot of the entity is borrowed mutably.
+    /// SAFETY: There must be no other reference to the component of the entity
+    pub unsafe fn get_tracked_mut_unchecked(
+        &self,
+        entity: Entity,
+        location: EntityLocation,
+        change_tick: u32,
+    ) -> Option<Mut<'_, T>> {
+        let slot = unsafe { self.value.as_ref() }.get_slot_ptr(entity, location)?;
+        let ComponentSlot { value, ticks } = unsafe { &mut *slot };
+        Some(Mut::new(value, ticks, change_tick))
     }
 }
 
 impl<T: Component> Deref for CARefMut<'_, T> {
-    type Target = Vec<Option<T>>;
+    type Target = ComponentStorage<T>;
 
     fn deref(&self) -> &Self::Target {
         unsafe { self.value.as_ref() }
@@ -173,10 +396,10 @@ impl<T: Component> DerefMut for CARefMut<'_, T> {
 
 /// CACell
 impl<T: Component> CACell<T> {
-    pub fn new() -> Self {
+    pub fn new(storage_type: StorageType) -> Self {
         Self {
-            borrow: Cell::new(UNUSED),
-            components: UnsafeCell::new(Vec::new()),
+            borrow: AtomicIsize::new(UNUSED),
+            components: UnsafeCell::new(ComponentStorage::new(storage_type)),
         }
     }
 
@@ -212,7 +435,7 @@ impl<T: Component> CACell<T> {
         }
     }
 
-    pub fn get_mut(&mut self) -> &mut Vec<Option<T>> {
+    pub fn get_mut(&mut self) -> &mut ComponentStorage<T> {
         self.components.get_mut()
     }
 }
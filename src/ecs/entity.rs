@@ -1,11 +1,27 @@
+use crate::ecs::EntityLocation;
+
 #[derive(Eq, Hash, PartialEq, Copy, Clone, Debug)]
-pub struct Entity(pub(in crate::ecs) usize);
+pub struct Entity {
+    index: usize,
+    generation: u32,
+}
 
 impl Entity {
-    pub(in crate::ecs) fn from_num(num: usize) -> Self {
-        Self(num)
+    pub(in crate::ecs) fn new(index: usize, generation: u32) -> Self {
+        Self { index, generation }
+    }
+    pub fn to_num(self) -> usize {
+        self.index
     }
-    pub fn to_num(&self) -> usize {
-        self.0
+    pub fn get_generation(&self) -> u32 {
+        self.generation
     }
 }
+
+/// EntityMeta
+#[derive(Copy, Clone)]
+pub(in crate::ecs) struct EntityMeta {
+    pub generation: u32,
+    pub alive: bool,
+    pub location: EntityLocation,
+}
@@ -0,0 +1,46 @@
+use crate::ecs::Entity;
+use std::error::Error;
+use std::fmt;
+
+/// EcsError
+/// Returned by try_ variants of World and Query, the other variants panic with it
+#[derive(Clone, Debug)]
+pub enum EcsError {
+    /// Component array or resource is already borrowed in a conflicting way
+    BorrowConflict(&'static str),
+    ComponentNotRegistered(&'static str),
+    ComponentAlreadyRegistered(&'static str),
+    /// Entity doesn't have the components of the query or is filtered out
+    MissingComponents {
+        entity: Entity,
+        query: &'static str,
+    },
+    MissingResource(&'static str),
+    DeadEntity(Entity),
+    /// Bundle contains the same component type more than once
+    DuplicateBundleComponent(&'static str),
+}
+
+impl fmt::Display for EcsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EcsError::BorrowConflict(name) => write!(f, "{} is already borrowed", name),
+            EcsError::ComponentNotRegistered(name) => {
+                write!(f, "Component {} is not registered", name)
+            }
+            EcsError::ComponentAlreadyRegistered(name) => {
+                write!(f, "Component {} is already registered", name)
+            }
+            EcsError::MissingComponents { entity, query } => {
+                write!(f, "Entity {:?} doesn't match query {}", entity, query)
+            }
+            EcsError::MissingResource(name) => write!(f, "Resource {} is not inserted", name),
+            EcsError::DeadEntity(entity) => write!(f, "Entity {:?} is not alive", entity),
+            EcsError::DuplicateBundleComponent(name) => {
+                write!(f, "Bundle {} contains a component type twice", name)
+            }
+        }
+    }
+}
+
+impl Error for EcsError {}
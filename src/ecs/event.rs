@@ -0,0 +1,113 @@
+use crate::ecs::{ResRef, ResRefMut};
+
+pub trait Event: Send + Sync + 'static {}
+
+impl<T: Send + Sync + 'static> Event for T {}
+
+/// Events
+/// Double-buffered queue of events of a single type, stored in World as a resource.
+/// Buffers are swapped once per frame, so an event can be read during the frame
+/// it was sent and the next one, then it's dropped
+pub struct Events<E: Event> {
+    previous: Vec<E>,
+    /// Id of the first event in the previous buffer
+    previous_start: usize,
+    current: Vec<E>,
+    current_start: usize,
+}
+
+impl<E: Event> Events<E> {
+    pub fn new() -> Self {
+        Self {
+            previous: Vec::new(),
+            previous_start: 0,
+            current: Vec::new(),
+            current_start: 0,
+        }
+    }
+
+    pub fn send(&mut self, event: E) {
+        self.current.push(event);
+    }
+
+    /// Drops events of the previous frame and starts a new buffer
+    pub fn update(&mut self) {
+        self.previous = std::mem::take(&mut self.current);
+        self.previous_start = self.current_start;
+        self.current_start += self.previous.len();
+    }
+
+    /// Id the next sent event gets
+    pub fn get_next_id(&self) -> usize {
+        self.current_start + self.current.len()
+    }
+
+    /// Events of both buffers with ids from first_id on
+    pub fn iter_from(&self, first_id: usize) -> impl Iterator<Item = &E> {
+        let skip_previous = first_id.saturating_sub(self.previous_start);
+        let skip_current = first_id.saturating_sub(self.current_start);
+        self.previous
+            .iter()
+            .skip(skip_previous)
+            .chain(self.current.iter().skip(skip_current))
+    }
+
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<E: Event> Default for Events<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// EventReader
+/// Reads events sent since its previous read. Every reader has its own cursor
+pub struct EventReader<'w, 's, E: Event> {
+    events: ResRef<'w, Events<E>>,
+    /// Id of the first unread event
+    cursor: &'s mut usize,
+}
+
+impl<'w, 's, E: Event> EventReader<'w, 's, E> {
+    pub fn new(events: ResRef<'w, Events<E>>, cursor: &'s mut usize) -> Self {
+        Self { events, cursor }
+    }
+
+    /// Events missed for more than a frame are not returned
+    pub fn read(&mut self) -> impl Iterator<Item = &E> {
+        let first_id = *self.cursor;
+        *self.cursor = self.events.get_next_id();
+        self.events.iter_from(first_id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.iter_from(*self.cursor).next().is_none()
+    }
+
+    /// Marks all events as read
+    pub fn clear(&mut self) {
+        *self.cursor = self.events.get_next_id();
+    }
+}
+
+/// EventWriter
+pub struct EventWriter<'w, E: Event> {
+    events: ResRefMut<'w, Events<E>>,
+}
+
+impl<'w, E: Event> EventWriter<'w, E> {
+    pub fn new(events: ResRefMut<'w, Events<E>>) -> Self {
+        Self { events }
+    }
+
+    pub fn send(&mut self, event: E) {
+        self.events.send(event);
+    }
+}
@@ -0,0 +1,235 @@
+use crate::ecs::{
+    Access, Archetype, Archetypes, CACell, Component, EcsError, Entity, EntityLocation, FetchResult, Fetcherable,
+    ParFetch, SystemTicks, World,
+};
+use std::marker::PhantomData;
+
+/// With
+/// Matches entities having T, doesn't borrow T
+pub struct With<T: Component>(PhantomData<T>);
+
+impl<T: Component> Fetcherable for With<T> {
+    type Item<'w> = ();
+    type ItemMut<'w> = ();
+    type Fetch<'w> = ();
+
+    fn try_fetch_init(_world: &World, _ticks: SystemTicks) -> Result<Self::Fetch<'_>, EcsError> {
+        Ok(())
+    }
+
+    fn matches_archetype(archetype: &Archetype) -> bool {
+        archetype.contains(T::get_type_id())
+    }
+
+    fn update_access(_access: &mut Access) {}
+
+    fn fetch_entity<'f, 'w: 'f>(
+        _fetch: &'f Self::Fetch<'w>,
+        _entity: Entity,
+        _location: EntityLocation,
+    ) -> FetchResult<Self::Item<'f>> {
+        FetchResult::Some(())
+    }
+
+    unsafe fn fetch_entity_mut<'f, 'w: 'f>(
+        _fetch: &'f Self::Fetch<'w>,
+        _entity: Entity,
+        _location: EntityLocation,
+    ) -> FetchResult<Self::ItemMut<'f>> {
+        FetchResult::Some(())
+    }
+}
+
+/// Without
+/// Matches entities not having T
+pub struct Without<T: Component>(PhantomData<T>);
+
+impl<T: Component> Fetcherable for Without<T> {
+    type Item<'w> = ();
+    type ItemMut<'w> = ();
+    type Fetch<'w> = ();
+
+    fn try_fetch_init(_world: &World, _ticks: SystemTicks) -> Result<Self::Fetch<'_>, EcsError> {
+        Ok(())
+    }
+
+    fn matches_archetype(archetype: &Archetype) -> bool {
+        !archetype.contains(T::get_type_id())
+    }
+
+    fn update_access(_access: &mut Access) {}
+
+    fn fetch_entity<'f, 'w: 'f>(
+        _fetch: &'f Self::Fetch<'w>,
+        _entity: Entity,
+        _location: EntityLocation,
+    ) -> FetchResult<Self::Item<'f>> {
+        FetchResult::Some(())
+    }
+
+    unsafe fn fetch_entity_mut<'f, 'w: 'f>(
+        _fetch: &'f Self::Fetch<'w>,
+        _entity: Entity,
+        _location: EntityLocation,
+    ) -> FetchResult<Self::ItemMut<'f>> {
+        FetchResult::Some(())
+    }
+}
+
+macro_rules! impl_tick_filter {
+    ($(#[$meta: meta])* $name: ident, $is_newer: ident) => {
+        $(#[$meta])*
+        pub struct $name<T: Component>(PhantomData<T>);
+
+        impl<T: Component> Fetcherable for $name<T> {
+            type Item<'w> = ();
+            type ItemMut<'w> = ();
+            type Fetch<'w> = (Option<&'w CACell<T>>, SystemTicks);
+
+            fn try_fetch_init<'w>(
+                world: &'w World,
+                ticks: SystemTicks,
+            ) -> Result<Self::Fetch<'w>, EcsError> {
+                Ok((world.try_get_queried_component_array::<T>()?, ticks))
+            }
+
+            fn matches_archetype(archetype: &Archetype) -> bool {
+                archetype.contains(T::get_type_id())
+            }
+
+            /// Ticks are read while other functions may write them
+            fn update_access(access: &mut Access) {
+                access.add_component_read::<T>();
+            }
+
+            fn filter_entity<'w>(
+                fetch: &Self::Fetch<'w>,
+                entity: Entity,
+                location: EntityLocation,
+            ) -> bool {
+                let (Some(components), ticks) = fetch else {
+                    return false;
+                };
+                // SAFETY: Filters are checked before any component of the entity is fetched
+                match unsafe { components.get_ticks_unchecked(entity, location) } {
+                    Some(component_ticks) => component_ticks.$is_newer(*ticks),
+                    None => false,
+                }
+            }
+
+            fn fetch_entity<'f, 'w: 'f>(
+                _fetch: &'f Self::Fetch<'w>,
+                _entity: Entity,
+                _location: EntityLocation,
+            ) -> FetchResult<Self::Item<'f>> {
+                FetchResult::Some(())
+            }
+
+            unsafe fn fetch_entity_mut<'f, 'w: 'f>(
+                _fetch: &'f Self::Fetch<'w>,
+                _entity: Entity,
+                _location: EntityLocation,
+            ) -> FetchResult<Self::ItemMut<'f>> {
+                FetchResult::Some(())
+            }
+        }
+    };
+}
+
+impl<T: Component> ParFetch for With<T> {}
+
+impl<T: Component> ParFetch for Without<T> {}
+
+impl_tick_filter!(
+    /// Added
+    /// Matches entities which got T since the last run of the logic function
+    Added,
+    is_added
+);
+
+impl_tick_filter!(
+    /// Changed
+    /// Matches entities whose T was added or mutably accessed since the last run of the
+    /// logic function
+    Changed,
+    is_changed
+);
+
+impl<T: Component> ParFetch for Added<T> {}
+
+impl<T: Component> ParFetch for Changed<T> {}
+
+/// Or
+/// Matches entities matched by any of the tuple members. Items of the members are dropped
+pub struct Or<T>(PhantomData<T>);
+
+macro_rules! impl_or_for_tuple {
+    ($($type_name: ident, $var_name: ident, $num: tt),*) => {
+        impl<$($type_name : Fetcherable, )*> Fetcherable for Or<($($type_name, )*)> {
+            type Item<'w> = ();
+            type ItemMut<'w> = ();
+            type Fetch<'w> = (&'w Archetypes, ($($type_name::Fetch<'w>, )*));
+
+            fn try_fetch_init<'w>(
+                world: &'w World,
+                ticks: SystemTicks,
+            ) -> Result<Self::Fetch<'w>, EcsError> {
+                Ok((world.get_archetypes(), ($($type_name::try_fetch_init(world, ticks)?, )*)))
+            }
+
+            fn matches_archetype(archetype: &Archetype) -> bool {
+                $($type_name::matches_archetype(archetype))||*
+            }
+
+            fn update_access(access: &mut Access) {
+                $($type_name::update_access(access);)*
+            }
+
+            fn filter_entity<'w>(
+                fetch: &Self::Fetch<'w>,
+                entity: Entity,
+                location: EntityLocation,
+            ) -> bool {
+                let archetype = fetch.0.get(location.archetype);
+                $(
+                    if $type_name::matches_archetype(archetype)
+                        && $type_name::filter_entity(&(fetch.1).$num, entity, location)
+                    {
+                        if let FetchResult::Some(_) =
+                            $type_name::fetch_entity(&(fetch.1).$num, entity, location)
+                        {
+                            return true;
+                        }
+                    }
+                )*
+                false
+            }
+
+            fn fetch_entity<'f, 'w: 'f>(
+                _fetch: &'f Self::Fetch<'w>,
+                _entity: Entity,
+                _location: EntityLocation,
+            ) -> FetchResult<Self::Item<'f>> {
+                FetchResult::Some(())
+            }
+
+            unsafe fn fetch_entity_mut<'f, 'w: 'f>(
+                _fetch: &'f Self::Fetch<'w>,
+                _entity: Entity,
+                _location: EntityLocation,
+            ) -> FetchResult<Self::ItemMut<'f>> {
+                FetchResult::Some(())
+            }
+        }
+    };
+}
+
+all_tuples!(impl_or_for_tuple);
+
+macro_rules! impl_par_fetch_for_or {
+    ($($type_name: ident, $var_name: ident, $num: tt),*) => {
+        impl<$($type_name : ParFetch, )*> ParFetch for Or<($($type_name, )*)> {}
+    };
+}
+
+all_tuples!(impl_par_fetch_for_or);
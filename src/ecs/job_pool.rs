@@ -0,0 +1,161 @@
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// JobPool
+/// Persistent worker threads. The calling thread takes part in the work too,
+/// so nested calls from a job can't deadlock
+pub struct JobPool {
+    sender: Option<Mutex<Sender<Job>>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl JobPool {
+    /// Pool with a worker for every core except the calling one
+    pub fn new() -> Self {
+        let cores = thread::available_parallelism().map_or(1, |n| n.get());
+        Self::with_workers(cores - 1)
+    }
+
+    /// With 0 workers every job runs on the calling thread
+    pub fn with_workers(count: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..count)
+            .map(|i| {
+                let receiver = receiver.clone();
+                thread::Builder::new()
+                    .name(format!("job-worker-{}", i))
+                    .spawn(move || Self::worker_loop(&receiver))
+                    .expect("Failed to spawn job worker")
+            })
+            .collect();
+        Self {
+            sender: Some(Mutex::new(sender)),
+            workers,
+        }
+    }
+
+    pub fn get_workers_count(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Calls f for every index in 0..count, spread over the workers and the calling thread.
+    /// Returns when all calls are done, a panic in any call is resumed on the calling thread
+    pub fn for_each_index<F: Fn(usize) + Sync>(&self, count: usize, f: F) {
+        if count == 0 {
+            return;
+        }
+        let next = AtomicUsize::new(0);
+        let work = || loop {
+            let index = next.fetch_add(1, Ordering::Relaxed);
+            if index >= count {
+                break;
+            }
+            f(index);
+        };
+        if count == 1 || self.workers.is_empty() {
+            work();
+            return;
+        }
+
+        let work: &(dyn Fn() + Sync) = &work;
+        // SAFETY: Workers only call the work while the batch is open, and the batch is
+        // closed with no active workers before this function returns
+        let work: &'static (dyn Fn() + Sync) = unsafe { std::mem::transmute(work) };
+        let batch = Arc::new(Batch {
+            work,
+            state: Mutex::new(BatchState {
+                closed: false,
+                active: 0,
+                panic: None,
+            }),
+            done: Condvar::new(),
+        });
+
+        let helpers = self.workers.len().min(count - 1);
+        {
+            let sender = self.sender.as_ref().unwrap().lock().unwrap();
+            for _ in 0..helpers {
+                let batch = batch.clone();
+                sender
+                    .send(Box::new(move || batch.help()))
+                    .expect("Job workers are gone");
+            }
+        }
+        batch.help();
+
+        let mut state = batch.state.lock().unwrap();
+        state.closed = true;
+        while state.active > 0 {
+            state = batch.done.wait(state).unwrap();
+        }
+        if let Some(payload) = state.panic.take() {
+            drop(state);
+            panic::resume_unwind(payload);
+        }
+    }
+
+    fn worker_loop(receiver: &Mutex<Receiver<Job>>) {
+        loop {
+            let job = receiver.lock().unwrap().recv();
+            match job {
+                Ok(job) => job(),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+impl Default for JobPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for JobPool {
+    fn drop(&mut self) {
+        self.sender = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Batch
+/// Work shared by the threads taking part in a single for_each_index call
+struct Batch {
+    work: &'static (dyn Fn() + Sync),
+    state: Mutex<BatchState>,
+    done: Condvar,
+}
+
+struct BatchState {
+    closed: bool,
+    active: usize,
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+impl Batch {
+    fn help(&self) {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.closed {
+                return;
+            }
+            state.active += 1;
+        }
+        let result = panic::catch_unwind(AssertUnwindSafe(|| (self.work)()));
+        let mut state = self.state.lock().unwrap();
+        state.active -= 1;
+        if let Err(payload) = result {
+            state.panic.get_or_insert(payload);
+        }
+        self.done.notify_all();
+    }
+}
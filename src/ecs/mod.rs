@@ -1,11 +1,48 @@
+/// Invokes the macro for tuples of 1 to 8 elements
+macro_rules! all_tuples {
+    ($m: ident) => {
+        $m!(T0, t0, 0);
+        $m!(T0, t0, 0, T1, t1, 1);
+        $m!(T0, t0, 0, T1, t1, 1, T2, t2, 2);
+        $m!(T0, t0, 0, T1, t1, 1, T2, t2, 2, T3, t3, 3);
+        $m!(T0, t0, 0, T1, t1, 1, T2, t2, 2, T3, t3, 3, T4, t4, 4);
+        $m!(T0, t0, 0, T1, t1, 1, T2, t2, 2, T3, t3, 3, T4, t4, 4, T5, t5, 5);
+        $m!(T0, t0, 0, T1, t1, 1, T2, t2, 2, T3, t3, 3, T4, t4, 4, T5, t5, 5, T6, t6, 6);
+        $m!(T0, t0, 0, T1, t1, 1, T2, t2, 2, T3, t3, 3, T4, t4, 4, T5, t5, 5, T6, t6, 6, T7, t7, 7);
+    };
+}
+
+mod access;
+mod archetype;
+mod bundle;
+mod change_detection;
+mod combinations;
 mod entity;
+mod error;
+mod event;
+mod filter;
+mod job_pool;
 mod component;
 mod component_array;
 mod world;
 mod query;
+mod resource;
+mod sparse_set;
 
+pub use access::*;
+pub use archetype::*;
+pub use bundle::*;
+pub use rustengine_derive::Bundle;
+pub use change_detection::*;
+pub use combinations::*;
 pub use entity::*;
+pub use error::*;
+pub use event::*;
+pub use filter::*;
+pub use job_pool::*;
 pub use component::*;
 pub use world::*;
 pub use component_array::*;
 pub use query::*;
+pub use resource::*;
+pub use sparse_set::*;
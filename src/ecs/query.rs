@@ -1,22 +1,94 @@
-use crate::ecs::{CARef, CARefMut, Component, Entity, World};
+use crate::ecs::{
+    Access, Archetype, ArchetypeId, Archetypes, CARef, CARefMut, Component, EcsError, Entity,
+    EntityLocation, Mut, QueryCombinationIter, QueryCombinationIterMut, SystemTicks, World,
+};
+use std::any::type_name;
 use std::marker::PhantomData;
+use std::ops::Range;
+
+/// Batches of parallel queries are never smaller, so small queries stay on one thread
+const PAR_BATCH_MIN_SIZE: usize = 1024;
 
 pub struct Query<'w, T: Fetcherable> {
     pub fetch: T::Fetch<'w>,
+    world: &'w World,
+    archetypes: Vec<ArchetypeId>,
 }
 
 impl<'w, T: Fetcherable> Query<'w, T> {
+    /// Query outside of a logic function, every component is treated as added and changed
     pub fn new(world: &'w World) -> Self {
-        let fetch = T::fetch_init(world);
-        Self { fetch }
+        let ticks = SystemTicks {
+            last_run: 0,
+            this_run: world.get_change_tick(),
+        };
+        Self::new_with_ticks(world, ticks)
+    }
+
+    /// Panics if a borrow conflicts with another query or a resource is missing
+    pub fn new_with_ticks(world: &'w World, ticks: SystemTicks) -> Self {
+        Self::try_new_with_ticks(world, ticks).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_new(world: &'w World) -> Result<Self, EcsError> {
+        let ticks = SystemTicks {
+            last_run: 0,
+            this_run: world.get_change_tick(),
+        };
+        Self::try_new_with_ticks(world, ticks)
+    }
+
+    pub fn try_new_with_ticks(world: &'w World, ticks: SystemTicks) -> Result<Self, EcsError> {
+        let fetch = T::try_fetch_init(world, ticks)?;
+        let archetypes = world
+            .get_archetypes()
+            .iter()
+            .filter(|archetype| T::matches_archetype(archetype))
+            .map(|archetype| archetype.get_id())
+            .collect();
+        Ok(Self {
+            fetch,
+            world,
+            archetypes,
+        })
     }
 
+    /// Returns FetchResult::None for stale entities
     pub fn fetch_entity<'q>(&'q self, entity: Entity) -> FetchResult<T::Item<'q>> {
-        T::fetch_entity(&self.fetch, entity)
+        match self.locate(entity) {
+            Some(location) => T::fetch_entity(&self.fetch, entity, location),
+            None => FetchResult::None,
+        }
+    }
+
+    /// Returns FetchResult::None for stale entities
+    pub fn fetch_entity_mut<'q>(&'q mut self, entity: Entity) -> FetchResult<T::ItemMut<'q>> {
+        match self.locate(entity) {
+            // SAFETY: The query is borrowed mutably while the item is alive
+            Some(location) => unsafe { T::fetch_entity_mut(&self.fetch, entity, location) },
+            None => FetchResult::None,
+        }
     }
 
-    pub fn fetch_entity_mut<'q>(&'q self, entity: Entity) -> FetchResult<T::ItemMut<'q>> {
-        T::fetch_entity_mut(&self.fetch, entity)
+    /// Like fetch_entity, but tells a dead entity from one the query doesn't match
+    pub fn try_fetch_entity<'q>(&'q self, entity: Entity) -> Result<T::Item<'q>, EcsError> {
+        let location = self.try_locate(entity)?;
+        match T::fetch_entity(&self.fetch, entity, location) {
+            FetchResult::Some(item) => Ok(item),
+            FetchResult::None => Err(Self::get_missing_error(entity)),
+        }
+    }
+
+    pub fn try_fetch_entity_mut<'q>(
+        &'q mut self,
+        entity: Entity,
+    ) -> Result<T::ItemMut<'q>, EcsError> {
+        let location = self.try_locate(entity)?;
+        // SAFETY: The query is borrowed mutably while the item is alive
+        match unsafe { T::fetch_entity_mut(&self.fetch, entity, location) } {
+            FetchResult::Some(item) => Ok(item),
+            FetchResult::None => Err(Self::get_missing_error(entity)),
+        }
     }
 
     pub fn iter<'q>(&'q self) -> QueryIter<'q, 'w, T> {
@@ -25,19 +97,191 @@ impl<'w, T: Fetcherable> Query<'w, T> {
     pub fn iter_mut<'q>(&'q mut self) -> QueryIterMut<'q, 'w, T> {
         QueryIterMut::<'q, 'w, T>::new(self)
     }
+
+    /// Every unordered set of K entities once
+    pub fn iter_combinations<'q, const K: usize>(&'q self) -> QueryCombinationIter<'q, 'w, T, K> {
+        QueryCombinationIter::new(self)
+    }
+
+    /// Every unordered set of K entities once, with mutable access to all of them
+    pub fn iter_combinations_mut<'q, const K: usize>(
+        &'q mut self,
+    ) -> QueryCombinationIterMut<'q, 'w, T, K> {
+        QueryCombinationIterMut::new(self)
+    }
+
+    /// Calls f for every entity on the job pool of the world. Only queries whose
+    /// items can be held by several threads at once, see ParFetch
+    pub fn par_for_each<F>(&self, f: F)
+    where
+        T: ParFetch,
+        F: Fn(QueryIterItem<'_, T>) + Sync,
+        T::Fetch<'w>: Sync,
+    {
+        let batches = self.get_par_batches();
+        self.world
+            .get_job_pool()
+            .for_each_index(batches.len(), |i| {
+                let (archetype, rows) = &batches[i];
+                for (entity, location) in self.get_batch_entities(*archetype, rows.clone()) {
+                    if let FetchResult::Some(c) = T::fetch_entity(&self.fetch, entity, location) {
+                        f(QueryIterItem {
+                            ent: entity,
+                            comp: c,
+                        });
+                    }
+                }
+            });
+    }
+
+    /// Mutable version of par_for_each, every entity is visited by a single thread
+    pub fn par_for_each_mut<F>(&mut self, f: F)
+    where
+        T: ParFetch,
+        F: Fn(QueryIterMutItem<'_, T>) + Sync,
+        T::Fetch<'w>: Sync,
+    {
+        let batches = self.get_par_batches();
+        let query = &*self;
+        query
+            .world
+            .get_job_pool()
+            .for_each_index(batches.len(), |i| {
+                let (archetype, rows) = &batches[i];
+                for (entity, location) in query.get_batch_entities(*archetype, rows.clone()) {
+                    // SAFETY: The query is borrowed mutably and batches don't overlap,
+                    // so every entity is fetched once
+                    if let FetchResult::Some(c) =
+                        unsafe { T::fetch_entity_mut(&query.fetch, entity, location) }
+                    {
+                        f(QueryIterMutItem {
+                            ent: entity,
+                            comp: c,
+                        });
+                    }
+                }
+            });
+    }
+
+    /// Splits rows of the matched archetypes into about 4 batches per thread
+    fn get_par_batches(&self) -> Vec<(ArchetypeId, Range<usize>)> {
+        let archetypes = self.world.get_archetypes();
+        let total: usize = self
+            .archetypes
+            .iter()
+            .map(|id| archetypes.get(*id).len())
+            .sum();
+        let threads = self.world.get_job_pool().get_workers_count() + 1;
+        let batch_size = PAR_BATCH_MIN_SIZE.max(total.div_ceil(threads * 4));
+
+        let mut batches = Vec::new();
+        for id in &self.archetypes {
+            let len = archetypes.get(*id).len();
+            let mut start = 0;
+            while start < len {
+                let end = len.min(start + batch_size);
+                batches.push((*id, start..end));
+                start = end;
+            }
+        }
+        batches
+    }
+
+    /// Entities of the archetype rows passing the filters
+    fn get_batch_entities(
+        &self,
+        archetype: ArchetypeId,
+        rows: Range<usize>,
+    ) -> impl Iterator<Item = (Entity, EntityLocation)> + use<'_, 'w, T> {
+        let entities = &self.world.get_archetypes().get(archetype).get_entities()[rows.clone()];
+        entities
+            .iter()
+            .zip(rows)
+            .map(move |(entity, row)| (*entity, EntityLocation { archetype, row }))
+            .filter(|(entity, location)| T::filter_entity(&self.fetch, *entity, *location))
+    }
+
+    /// Entities passing archetype matching and filters
+    pub(in crate::ecs) fn get_matched_entities(&self) -> Vec<(Entity, EntityLocation)> {
+        let mut cursor = ArchetypeCursor::new();
+        let mut entities = Vec::new();
+        while let Some((entity, location)) = cursor.next(self) {
+            if T::filter_entity(&self.fetch, entity, location) {
+                entities.push((entity, location));
+            }
+        }
+        entities
+    }
+
+    fn locate(&self, entity: Entity) -> Option<EntityLocation> {
+        let location = self.world.get_entity_location(entity)?;
+        let archetype = self.world.get_archetypes().get(location.archetype);
+        if T::matches_archetype(archetype) && T::filter_entity(&self.fetch, entity, location) {
+            Some(location)
+        } else {
+            None
+        }
+    }
+
+    fn try_locate(&self, entity: Entity) -> Result<EntityLocation, EcsError> {
+        if !self.world.is_alive(entity) {
+            return Err(EcsError::DeadEntity(entity));
+        }
+        self.locate(entity)
+            .ok_or_else(|| Self::get_missing_error(entity))
+    }
+
+    fn get_missing_error(entity: Entity) -> EcsError {
+        EcsError::MissingComponents {
+            entity,
+            query: type_name::<T>(),
+        }
+    }
+}
+
+/// ArchetypeCursor
+/// Walks rows of the archetypes matched by a query
+struct ArchetypeCursor {
+    archetype_index: usize,
+    row: usize,
+}
+
+impl ArchetypeCursor {
+    fn new() -> Self {
+        Self {
+            archetype_index: 0,
+            row: 0,
+        }
+    }
+
+    fn next<T: Fetcherable>(&mut self, query: &Query<'_, T>) -> Option<(Entity, EntityLocation)> {
+        loop {
+            let id = *query.archetypes.get(self.archetype_index)?;
+            let archetype = query.world.get_archetypes().get(id);
+            if self.row >= archetype.len() {
+                self.archetype_index += 1;
+                self.row = 0;
+                continue;
+            }
+            let row = self.row;
+            self.row += 1;
+            let location = EntityLocation { archetype: id, row };
+            return Some((archetype.get_entities()[row], location));
+        }
+    }
 }
 
 /// QueryIter
 pub struct QueryIter<'q, 'w: 'q, T: Fetcherable> {
     query: &'q Query<'w, T>,
-    cur_entity: Entity,
+    cursor: ArchetypeCursor,
 }
 
 impl<'q, 'w: 'q, T: Fetcherable> QueryIter<'q, 'w, T> {
     pub fn new(query: &'q Query<'w, T>) -> Self {
         Self {
             query,
-            cur_entity: Entity::from_num(0),
+            cursor: ArchetypeCursor::new(),
         }
     }
 }
@@ -51,30 +295,25 @@ impl<'q, 'w: 'q, T: Fetcherable> Iterator for QueryIter<'q, 'w, T> {
     type Item = QueryIterItem<'q, T>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut cur_entity = self.cur_entity;
-        let next = loop {
-            match self.query.fetch_entity(cur_entity) {
-                FetchResult::Some(c) => {
-                    break Some(QueryIterItem {
-                        ent: cur_entity,
-                        comp: c,
-                    });
-                }
-                FetchResult::None => cur_entity.0 += 1,
-                FetchResult::End => {
-                    break None;
-                }
+        while let Some((entity, location)) = self.cursor.next(self.query) {
+            if !T::filter_entity(&self.query.fetch, entity, location) {
+                continue;
             }
-        };
-        self.cur_entity.0 += 1;
-        next
+            if let FetchResult::Some(c) = T::fetch_entity(&self.query.fetch, entity, location) {
+                return Some(QueryIterItem {
+                    ent: entity,
+                    comp: c,
+                });
+            }
+        }
+        None
     }
 }
 
 /// QueryIterMut
 pub struct QueryIterMut<'q, 'w: 'q, T: Fetcherable> {
     query: &'q Query<'w, T>,
-    cur_entity: Entity,
+    cursor: ArchetypeCursor,
     marker: PhantomData<&'q mut Query<'w, T>>,
 }
 
@@ -82,7 +321,7 @@ impl<'q, 'w: 'q, T: Fetcherable> QueryIterMut<'q, 'w, T> {
     pub fn new(query: &'q Query<'w, T>) -> Self {
         Self {
             query,
-            cur_entity: Entity::from_num(0),
+            cursor: ArchetypeCursor::new(),
             marker: PhantomData,
         }
     }
@@ -97,23 +336,21 @@ impl<'q, 'w: 'q, T: Fetcherable> Iterator for QueryIterMut<'q, 'w, T> {
     type Item = QueryIterMutItem<'q, T>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut cur_entity = self.cur_entity;
-        let next = loop {
-            match self.query.fetch_entity_mut(cur_entity) {
-                FetchResult::Some(c) => {
-                    break Some(QueryIterMutItem {
-                        ent: cur_entity,
-                        comp: c,
-                    });
-                }
-                FetchResult::None => cur_entity.0 += 1,
-                FetchResult::End => {
-                    break None;
-                }
+        while let Some((entity, location)) = self.cursor.next(self.query) {
+            if !T::filter_entity(&self.query.fetch, entity, location) {
+                continue;
             }
-        };
-        self.cur_entity.0 += 1;
-        next
+            // SAFETY: The iterator borrows the query mutably and visits every entity once
+            if let FetchResult::Some(c) =
+                unsafe { T::fetch_entity_mut(&self.query.fetch, entity, location) }
+            {
+                return Some(QueryIterMutItem {
+                    ent: entity,
+                    comp: c,
+                });
+            }
+        }
+        None
     }
 }
 
@@ -121,7 +358,6 @@ impl<'q, 'w: 'q, T: Fetcherable> Iterator for QueryIterMut<'q, 'w, T> {
 pub enum FetchResult<T> {
     Some(T),
     None,
-    End,
 }
 
 /// Fetcherable
@@ -130,165 +366,290 @@ pub trait Fetcherable {
     type ItemMut<'w>;
     type Fetch<'w>;
 
-    fn fetch_init<'w>(world: &'w World) -> Self::Fetch<'w>;
+    /// Fails if a borrowed component array or resource is already borrowed in a
+    /// conflicting way, or is missing
+    fn try_fetch_init<'w>(world: &'w World, ticks: SystemTicks)
+        -> Result<Self::Fetch<'w>, EcsError>;
+
+    /// Panics where try_fetch_init fails
+    fn fetch_init<'w>(world: &'w World, ticks: SystemTicks) -> Self::Fetch<'w> {
+        Self::try_fetch_init(world, ticks).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Only entities of matching archetypes are fetched
+    fn matches_archetype(archetype: &Archetype) -> bool;
+
+    /// Adds components and resources borrowed by the fetch, used for scheduling
+    fn update_access(access: &mut Access);
+
+    /// Per entity check done for all tuple members before fetching any of them
+    fn filter_entity<'w>(
+        _fetch: &Self::Fetch<'w>,
+        _entity: Entity,
+        _location: EntityLocation,
+    ) -> bool {
+        true
+    }
 
     fn fetch_entity<'f, 'w: 'f>(
         fetch: &'f Self::Fetch<'w>,
         entity: Entity,
+        location: EntityLocation,
     ) -> FetchResult<Self::Item<'f>>;
 
-    fn fetch_entity_mut<'f, 'w: 'f>(
+    /// Mutable items are got through a shared fetch, so that distinct entities
+    /// can be fetched at the same time.
+    /// # Safety
+    /// No other item of the entity fetched from the same fetch may be alive while
+    /// the returned item is, and no shared item of any entity either
+    unsafe fn fetch_entity_mut<'f, 'w: 'f>(
         fetch: &'f Self::Fetch<'w>,
         entity: Entity,
+        location: EntityLocation,
     ) -> FetchResult<Self::ItemMut<'f>>;
 }
 
+/// ParFetch
+/// Fetcherable whose items of different entities can be held by several threads at once.
+/// Not implemented by ResMut, every item of which borrows the whole resource
+pub trait ParFetch: Fetcherable {}
+
+impl<T: Component> ParFetch for &T {}
+
+impl<T: Component> ParFetch for &mut T {}
+
+impl<F: ParFetch> ParFetch for Option<F> {}
+
 /// Fetcherable for &T
 impl<T: Component> Fetcherable for &T {
     type Item<'w> = &'w T;
     type ItemMut<'w> = &'w T;
-    type Fetch<'w> = CARef<'w, T>;
+    type Fetch<'w> = Option<CARef<'w, T>>;
+
+    fn try_fetch_init<'w>(
+        world: &'w World,
+        _ticks: SystemTicks,
+    ) -> Result<Self::Fetch<'w>, EcsError> {
+        world
+            .try_get_queried_component_array::<T>()?
+            .map(|components| {
+                components
+                    .try_borrow()
+                    .ok_or(EcsError::BorrowConflict(type_name::<T>()))
+            })
+            .transpose()
+    }
+
+    fn matches_archetype(archetype: &Archetype) -> bool {
+        archetype.contains(T::get_type_id())
+    }
 
-    fn fetch_init<'w>(world: &'w World) -> Self::Fetch<'w> {
-        world.get_component_array::<T>().unwrap().borrow()
+    fn update_access(access: &mut Access) {
+        access.add_component_read::<T>();
     }
 
     fn fetch_entity<'f, 'w: 'f>(
         fetch: &'f Self::Fetch<'w>,
         entity: Entity,
+        location: EntityLocation,
     ) -> FetchResult<Self::Item<'f>> {
-        match fetch.get(entity.to_num()) {
-            None => FetchResult::End,
-            Some(comp) => match comp {
-                None => FetchResult::None,
-                Some(comp) => FetchResult::Some(comp),
-            },
+        match fetch.as_ref().and_then(|c| c.get(entity, location)) {
+            None => FetchResult::None,
+            Some(comp) => FetchResult::Some(comp),
         }
     }
 
-    fn fetch_entity_mut<'f, 'w: 'f>(
+    unsafe fn fetch_entity_mut<'f, 'w: 'f>(
         fetch: &'f Self::Fetch<'w>,
         entity: Entity,
+        location: EntityLocation,
     ) -> FetchResult<Self::ItemMut<'f>> {
-        match fetch.get(entity.to_num()) {
-            None => FetchResult::End,
-            Some(comp) => match comp {
-                None => FetchResult::None,
-                Some(comp) => FetchResult::Some(comp),
-            },
-        }
+        Self::fetch_entity(fetch, entity, location)
     }
 }
 
 /// Fetcherable for &mut T
 impl<T: Component> Fetcherable for &mut T {
     type Item<'w> = &'w T;
-    type ItemMut<'w> = &'w mut T;
-    type Fetch<'w> = CARefMut<'w, T>;
+    type ItemMut<'w> = Mut<'w, T>;
+    type Fetch<'w> = (Option<CARefMut<'w, T>>, SystemTicks);
+
+    fn try_fetch_init<'w>(
+        world: &'w World,
+        ticks: SystemTicks,
+    ) -> Result<Self::Fetch<'w>, EcsError> {
+        let components = world
+            .try_get_queried_component_array::<T>()?
+            .map(|components| {
+                components
+                    .try_borrow_mut()
+                    .ok_or(EcsError::BorrowConflict(type_name::<T>()))
+            })
+            .transpose()?;
+        Ok((components, ticks))
+    }
 
-    fn fetch_init<'w>(world: &'w World) -> Self::Fetch<'w> {
-        world
-            .get_component_array::<T>()
-            .expect("No such component")
-            .borrow_mut()
+    fn matches_archetype(archetype: &Archetype) -> bool {
+        archetype.contains(T::get_type_id())
+    }
+
+    fn update_access(access: &mut Access) {
+        access.add_component_write::<T>();
     }
 
     fn fetch_entity<'f, 'w: 'f>(
         fetch: &'f Self::Fetch<'w>,
         entity: Entity,
+        location: EntityLocation,
     ) -> FetchResult<Self::Item<'f>> {
-        match fetch.get(entity.to_num()) {
-            None => FetchResult::End,
-            Some(comp) => match comp {
-                None => FetchResult::None,
-                Some(comp) => FetchResult::Some(comp),
-            },
+        match fetch.0.as_ref().and_then(|c| c.get(entity, location)) {
+            None => FetchResult::None,
+            Some(comp) => FetchResult::Some(comp),
         }
     }
 
-    fn fetch_entity_mut<'f, 'w: 'f>(
+    unsafe fn fetch_entity_mut<'f, 'w: 'f>(
         fetch: &'f Self::Fetch<'w>,
         entity: Entity,
+        location: EntityLocation,
     ) -> FetchResult<Self::ItemMut<'f>> {
+        let (Some(components), ticks) = fetch else {
+            return FetchResult::None;
+        };
+        // SAFETY: The caller guarantees no other item of the entity is alive
         unsafe {
-            match fetch.deref_mut_unsafe().get_mut(entity.to_num()) {
-                None => FetchResult::End,
-                Some(comp) => match comp {
-                    None => FetchResult::None,
-                    Some(comp) => FetchResult::Some(comp),
-                },
+            match components.get_tracked_mut_unchecked(entity, location, ticks.this_run) {
+                None => FetchResult::None,
+                Some(comp) => FetchResult::Some(comp),
             }
         }
     }
 }
 
-/// Fetcherable for tuple
-macro_rules! impl_fetch_helper_1 {
-    ($a: tt) => {
-        FetchResult::None
-    };
-}
+/// Fetcherable for Option
+/// Matches every entity, yields None if the inner query doesn't match the entity
+impl<F: Fetcherable> Fetcherable for Option<F> {
+    type Item<'w> = Option<F::Item<'w>>;
+    type ItemMut<'w> = Option<F::ItemMut<'w>>;
+    type Fetch<'w> = (&'w Archetypes, F::Fetch<'w>);
+
+    fn try_fetch_init<'w>(
+        world: &'w World,
+        ticks: SystemTicks,
+    ) -> Result<Self::Fetch<'w>, EcsError> {
+        Ok((world.get_archetypes(), F::try_fetch_init(world, ticks)?))
+    }
 
-macro_rules! impl_fetch_helper_2 {
-    ($a: tt) => {
-        _
-    };
+    fn matches_archetype(_archetype: &Archetype) -> bool {
+        true
+    }
+
+    fn update_access(access: &mut Access) {
+        F::update_access(access);
+    }
+
+    fn fetch_entity<'f, 'w: 'f>(
+        fetch: &'f Self::Fetch<'w>,
+        entity: Entity,
+        location: EntityLocation,
+    ) -> FetchResult<Self::Item<'f>> {
+        if !F::matches_archetype(fetch.0.get(location.archetype))
+            || !F::filter_entity(&fetch.1, entity, location)
+        {
+            return FetchResult::Some(None);
+        }
+        match F::fetch_entity(&fetch.1, entity, location) {
+            FetchResult::Some(item) => FetchResult::Some(Some(item)),
+            FetchResult::None => FetchResult::Some(None),
+        }
+    }
+
+    unsafe fn fetch_entity_mut<'f, 'w: 'f>(
+        fetch: &'f Self::Fetch<'w>,
+        entity: Entity,
+        location: EntityLocation,
+    ) -> FetchResult<Self::ItemMut<'f>> {
+        if !F::matches_archetype(fetch.0.get(location.archetype))
+            || !F::filter_entity(&fetch.1, entity, location)
+        {
+            return FetchResult::Some(None);
+        }
+        // SAFETY: Same contract as the caller's
+        match unsafe { F::fetch_entity_mut(&fetch.1, entity, location) } {
+            FetchResult::Some(item) => FetchResult::Some(Some(item)),
+            FetchResult::None => FetchResult::Some(None),
+        }
+    }
 }
 
+/// Fetcherable for tuple
 macro_rules! impl_fetch_for_tuple {
     ($($type_name: ident, $var_name: ident, $num: tt),*) => {
-        // impl<'a, $($type_name: 'static,)*> Fetch<'a> for ($($type_name, )*){
-        //     type Item = ($(&'a $type_name,)*);
-        // }
         impl<$($type_name : Fetcherable, )*> Fetcherable for ($($type_name, )*) {
             type Item<'w> = ($($type_name::Item<'w>, )*);
             type ItemMut<'w> = ($($type_name::ItemMut<'w>, )*);
             type Fetch<'w> = ($($type_name::Fetch<'w>, )*);
 
-            fn fetch_init<'w>(world: &'w World) -> Self::Fetch<'w> {
-                ($($type_name::fetch_init(world), )*)
+            fn try_fetch_init<'w>(
+                world: &'w World,
+                ticks: SystemTicks,
+            ) -> Result<Self::Fetch<'w>, EcsError> {
+                Ok(($($type_name::try_fetch_init(world, ticks)?, )*))
+            }
+
+            fn matches_archetype(archetype: &Archetype) -> bool {
+                $($type_name::matches_archetype(archetype))&&*
+            }
+
+            fn update_access(access: &mut Access) {
+                $($type_name::update_access(access);)*
+            }
+
+            fn filter_entity<'w>(
+                fetch: &Self::Fetch<'w>,
+                entity: Entity,
+                location: EntityLocation,
+            ) -> bool {
+                $($type_name::filter_entity(&fetch.$num, entity, location))&&*
             }
 
             fn fetch_entity<'f, 'w: 'f>(
                 fetch: &'f Self::Fetch<'w>,
                 entity: Entity,
+                location: EntityLocation,
             ) -> FetchResult<Self::Item<'f>> {
                 match (
-                    $($type_name::fetch_entity(&fetch.$num, entity), )*
+                    $($type_name::fetch_entity(&fetch.$num, entity, location), )*
                 ) {
                     ($(FetchResult::Some($var_name),)*) => FetchResult::Some(($($var_name, )*)),
-                    ($(impl_fetch_helper_1!($var_name), )*) => FetchResult::None,
-                    ($(impl_fetch_helper_2!($var_name), )*) => FetchResult::End,
+                    _ => FetchResult::None,
                 }
             }
 
-            fn fetch_entity_mut<'f, 'w: 'f>(
+            unsafe fn fetch_entity_mut<'f, 'w: 'f>(
                 fetch: &'f Self::Fetch<'w>,
                 entity: Entity,
+                location: EntityLocation,
             ) -> FetchResult<Self::ItemMut<'f>> {
-                match (
-                    $($type_name::fetch_entity_mut(&fetch.$num, entity), )*
-                ) {
+                // SAFETY: Same contract as the caller's. Members can't share a component
+                // or resource, borrowing it twice fails when the query is created
+                match unsafe { (
+                    $($type_name::fetch_entity_mut(&fetch.$num, entity, location), )*
+                ) } {
                     ($(FetchResult::Some($var_name),)*) => FetchResult::Some(($($var_name, )*)),
-                    ($(impl_fetch_helper_1!($var_name), )*) => FetchResult::None,
-                    ($(impl_fetch_helper_2!($var_name), )*) => FetchResult::End,
+                    _ => FetchResult::None,
                 }
             }
         }
     };
 }
 
-macro_rules! all_tuples {
-    ($m: ident) => {
-        $m!(T0, t0, 0);
-        $m!(T0, t0, 0, T1, t1, 1);
-        $m!(T0, t0, 0, T1, t1, 1, T2, t2, 2);
-        $m!(T0, t0, 0, T1, t1, 1, T2, t2, 2, T3, t3, 3);
-        $m!(T0, t0, 0, T1, t1, 1, T2, t2, 2, T3, t3, 3, T4, t4, 4);
-        $m!(T0, t0, 0, T1, t1, 1, T2, t2, 2, T3, t3, 3, T4, t4, 4, T5, t5, 5);
-        $m!(T0, t0, 0, T1, t1, 1, T2, t2, 2, T3, t3, 3, T4, t4, 4, T5, t5, 5, T6, t6, 6);
-        $m!(T0, t0, 0, T1, t1, 1, T2, t2, 2, T3, t3, 3, T4, t4, 4, T5, t5, 5, T6, t6, 6, T7, t7, 7);
+all_tuples!(impl_fetch_for_tuple);
+
+macro_rules! impl_par_fetch_for_tuple {
+    ($($type_name: ident, $var_name: ident, $num: tt),*) => {
+        impl<$($type_name : ParFetch, )*> ParFetch for ($($type_name, )*) {}
     };
 }
 
-all_tuples!(impl_fetch_for_tuple);
+all_tuples!(impl_par_fetch_for_tuple);
@@ -0,0 +1,238 @@
+use crate::ecs::{
+    Access, Archetype, CABorrow, CABorrowMut, EcsError, Entity, EntityLocation, FetchResult, Fetcherable,
+    ParFetch, SystemTicks, World, UNUSED,
+};
+use std::any::{type_name, Any, TypeId};
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::AtomicIsize;
+
+pub trait Resource: Send + Sync + 'static {
+    fn get_type_id() -> TypeId;
+}
+
+impl<T: Send + Sync + 'static> Resource for T {
+    fn get_type_id() -> TypeId {
+        TypeId::of::<T>()
+    }
+}
+
+/// ResourceCell
+/// Same borrow rules as CACell: any number of readers or a single writer
+pub struct ResourceCell<R: Resource> {
+    borrow: AtomicIsize,
+    value: UnsafeCell<R>,
+}
+
+// SAFETY: Access to the value is guarded by the atomic borrow flag
+unsafe impl<R: Resource> Sync for ResourceCell<R> {}
+
+impl<R: Resource> ResourceCell<R> {
+    pub(in crate::ecs) fn new(value: R) -> Self {
+        Self {
+            borrow: AtomicIsize::new(UNUSED),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub(in crate::ecs) fn into_inner(self) -> R {
+        self.value.into_inner()
+    }
+
+    pub fn borrow(&self) -> ResRef<'_, R> {
+        match self.try_borrow() {
+            Some(r) => r,
+            None => panic!("Resource {} is already mutably borrowed", type_name::<R>()),
+        }
+    }
+
+    pub fn borrow_mut(&self) -> ResRefMut<'_, R> {
+        match self.try_borrow_mut() {
+            Some(r) => r,
+            None => panic!("Resource {} is already borrowed", type_name::<R>()),
+        }
+    }
+
+    pub fn try_borrow(&self) -> Option<ResRef<'_, R>> {
+        let borrow = CABorrow::new(&self.borrow)?;
+        // SAFETY: Shared borrow is registered in the flag
+        let value = unsafe { &*self.value.get() };
+        Some(ResRef {
+            value,
+            _borrow: borrow,
+        })
+    }
+
+    pub fn try_borrow_mut(&self) -> Option<ResRefMut<'_, R>> {
+        let borrow = CABorrowMut::new(&self.borrow)?;
+        // SAFETY: Exclusive borrow is registered in the flag
+        let value = unsafe { &mut *self.value.get() };
+        Some(ResRefMut {
+            value,
+            _borrow: borrow,
+        })
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        self.value.get_mut()
+    }
+}
+
+/// ResRef
+pub struct ResRef<'b, R: Resource> {
+    value: &'b R,
+    _borrow: CABorrow<'b>,
+}
+
+impl<R: Resource> Deref for ResRef<'_, R> {
+    type Target = R;
+
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+/// ResRefMut
+pub struct ResRefMut<'b, R: Resource> {
+    value: &'b mut R,
+    _borrow: CABorrowMut<'b>,
+}
+
+impl<R: Resource> Deref for ResRefMut<'_, R> {
+    type Target = R;
+
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+impl<R: Resource> DerefMut for ResRefMut<'_, R> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.value
+    }
+}
+
+/// Resources
+pub(in crate::ecs) struct Resources {
+    cells: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl Resources {
+    pub fn new() -> Self {
+        Self {
+            cells: HashMap::new(),
+        }
+    }
+
+    pub fn insert<R: Resource>(&mut self, value: R) {
+        self.cells
+            .insert(R::get_type_id(), Box::new(ResourceCell::new(value)));
+    }
+
+    pub fn remove<R: Resource>(&mut self) -> Option<R> {
+        let cell = self.cells.remove(&R::get_type_id())?;
+        let cell = cell.downcast::<ResourceCell<R>>().ok()?;
+        Some(cell.into_inner())
+    }
+
+    pub fn contains<R: Resource>(&self) -> bool {
+        self.cells.contains_key(&R::get_type_id())
+    }
+
+    pub fn get_cell<R: Resource>(&self) -> Option<&ResourceCell<R>> {
+        self.cells.get(&R::get_type_id())?.downcast_ref()
+    }
+
+    pub fn get_cell_mut<R: Resource>(&mut self) -> Option<&mut ResourceCell<R>> {
+        self.cells.get_mut(&R::get_type_id())?.downcast_mut()
+    }
+}
+
+/// Res
+/// Shared access to a resource for every fetched entity, borrowed for the lifetime of the query
+pub struct Res<R: Resource>(PhantomData<R>);
+
+impl<R: Resource> Fetcherable for Res<R> {
+    type Item<'w> = &'w R;
+    type ItemMut<'w> = &'w R;
+    type Fetch<'w> = ResRef<'w, R>;
+
+    fn try_fetch_init<'w>(
+        world: &'w World,
+        _ticks: SystemTicks,
+    ) -> Result<Self::Fetch<'w>, EcsError> {
+        world.try_resource::<R>()
+    }
+
+    fn matches_archetype(_archetype: &Archetype) -> bool {
+        true
+    }
+
+    fn update_access(access: &mut Access) {
+        access.add_resource_read::<R>();
+    }
+
+    fn fetch_entity<'f, 'w: 'f>(
+        fetch: &'f Self::Fetch<'w>,
+        _entity: Entity,
+        _location: EntityLocation,
+    ) -> FetchResult<Self::Item<'f>> {
+        FetchResult::Some(fetch)
+    }
+
+    unsafe fn fetch_entity_mut<'f, 'w: 'f>(
+        fetch: &'f Self::Fetch<'w>,
+        _entity: Entity,
+        _location: EntityLocation,
+    ) -> FetchResult<Self::ItemMut<'f>> {
+        FetchResult::Some(fetch)
+    }
+}
+
+impl<R: Resource> ParFetch for Res<R> {}
+
+/// ResMut
+/// Every item borrows the resource while alive, so holding items of two entities at once
+/// panics instead of aliasing the resource. Not ParFetch, so it can't be in parallel queries
+pub struct ResMut<R: Resource>(PhantomData<R>);
+
+impl<R: Resource> Fetcherable for ResMut<R> {
+    type Item<'w> = ResRef<'w, R>;
+    type ItemMut<'w> = ResRefMut<'w, R>;
+    type Fetch<'w> = &'w ResourceCell<R>;
+
+    fn try_fetch_init<'w>(
+        world: &'w World,
+        _ticks: SystemTicks,
+    ) -> Result<Self::Fetch<'w>, EcsError> {
+        world
+            .get_resource_cell::<R>()
+            .ok_or(EcsError::MissingResource(type_name::<R>()))
+    }
+
+    fn matches_archetype(_archetype: &Archetype) -> bool {
+        true
+    }
+
+    fn update_access(access: &mut Access) {
+        access.add_resource_write::<R>();
+    }
+
+    fn fetch_entity<'f, 'w: 'f>(
+        fetch: &'f Self::Fetch<'w>,
+        _entity: Entity,
+        _location: EntityLocation,
+    ) -> FetchResult<Self::Item<'f>> {
+        FetchResult::Some(fetch.borrow())
+    }
+
+    unsafe fn fetch_entity_mut<'f, 'w: 'f>(
+        fetch: &'f Self::Fetch<'w>,
+        _entity: Entity,
+        _location: EntityLocation,
+    ) -> FetchResult<Self::ItemMut<'f>> {
+        FetchResult::Some(fetch.borrow_mut())
+    }
+}
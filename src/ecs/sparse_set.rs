@@ -0,0 +1,88 @@
+use crate::ecs::Entity;
+
+const EMPTY: usize = usize::MAX;
+
+/// SparseSet
+/// Components are packed in a dense array, sparse array maps entity index to its position
+/// in the dense array. Sparse array grows only up to the largest stored entity index
+pub struct SparseSet<T> {
+    sparse: Vec<usize>,
+    dense: Vec<T>,
+    entities: Vec<Entity>,
+}
+
+impl<T> SparseSet<T> {
+    pub fn new() -> Self {
+        Self {
+            sparse: Vec::new(),
+            dense: Vec::new(),
+            entities: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.dense.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dense.is_empty()
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.get_dense_index(entity).is_some()
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        let index = self.get_dense_index(entity)?;
+        Some(&self.dense[index])
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        let index = self.get_dense_index(entity)?;
+        Some(&mut self.dense[index])
+    }
+
+    /// Pointer into the dense array, doesn't borrow the set mutably
+    pub fn get_ptr(&self, entity: Entity) -> Option<*mut T> {
+        let index = self.get_dense_index(entity)?;
+        Some(unsafe { self.dense.as_ptr().add(index) as *mut T })
+    }
+
+    pub fn get_entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    pub fn insert(&mut self, entity: Entity, component: T) {
+        if let Some(index) = self.get_dense_index(entity) {
+            self.dense[index] = component;
+            self.entities[index] = entity;
+            return;
+        }
+
+        let sparse_index = entity.to_num();
+        if self.sparse.len() <= sparse_index {
+            self.sparse.resize(sparse_index + 1, EMPTY);
+        }
+        self.sparse[sparse_index] = self.dense.len();
+        self.dense.push(component);
+        self.entities.push(entity);
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> Option<T> {
+        let index = self.get_dense_index(entity)?;
+        self.sparse[entity.to_num()] = EMPTY;
+        self.entities.swap_remove(index);
+        let component = self.dense.swap_remove(index);
+        if let Some(moved) = self.entities.get(index) {
+            self.sparse[moved.to_num()] = index;
+        }
+        Some(component)
+    }
+
+    fn get_dense_index(&self, entity: Entity) -> Option<usize> {
+        match self.sparse.get(entity.to_num()) {
+            Some(&index) if index != EMPTY => Some(index),
+            _ => None,
+        }
+    }
+}
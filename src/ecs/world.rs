@@ -1,59 +1,377 @@
-use crate::ecs::component::Component;
-use crate::ecs::entity::Entity;
-use crate::ecs::{CACell, ComponentArray, Fetcherable, Query};
-use std::any::TypeId;
+use crate::ecs::component::{Component, StorageType};
+use crate::ecs::entity::{Entity, EntityMeta};
+use crate::ecs::{
+    ArchetypeId, Archetypes, Bundle, CACell, ComponentArray, EcsError, EntityLocation, Event, Events,
+    Fetcherable, JobPool, Query, ResRef, ResRefMut, Resource, ResourceCell, Resources, SystemTicks,
+};
+use std::any::{type_name, TypeId};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::OnceLock;
 
 pub struct World {
-    entities_count: usize,
+    entities: Vec<EntityMeta>,
+    free_entities: Vec<usize>,
+    reserved_entities: AtomicUsize,
+    archetypes: Archetypes,
     component_arrays: HashMap<TypeId, Box<dyn ComponentArray>>,
+    default_storage: StorageType,
+    strict_registration: bool,
+    change_tick: AtomicU32,
+    resources: Resources,
+    /// Swap buffers of every added Events<E>
+    event_updaters: Vec<fn(&mut World)>,
+    job_pool: OnceLock<JobPool>,
 }
 
 impl World {
     pub fn new() -> Self {
         Self {
-            entities_count: 0,
+            entities: Vec::new(),
+            free_entities: Vec::new(),
+            reserved_entities: AtomicUsize::new(0),
+            archetypes: Archetypes::new(),
             component_arrays: HashMap::new(),
+            default_storage: StorageType::Dense,
+            strict_registration: false,
+            change_tick: AtomicU32::new(1),
+            resources: Resources::new(),
+            event_updaters: Vec::new(),
+            job_pool: OnceLock::new(),
         }
     }
 
+    /// Storage used by components registered after this call
+    pub fn set_default_storage(&mut self, storage_type: StorageType) {
+        self.default_storage = storage_type;
+    }
+
+    pub fn get_default_storage(&self) -> StorageType {
+        self.default_storage
+    }
+
+    pub fn get_change_tick(&self) -> u32 {
+        self.change_tick.load(Ordering::Relaxed)
+    }
+
+    /// Returns the tick before increment, to be used as the tick of a running function
+    pub fn increment_change_tick(&self) -> u32 {
+        self.change_tick.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Pool used by parallel queries, created on first use
+    pub fn get_job_pool(&self) -> &JobPool {
+        self.job_pool.get_or_init(JobPool::new)
+    }
+
+    /// Replaces the pool, e.g. to limit the number of threads used by parallel queries
+    pub fn set_job_pool(&mut self, job_pool: JobPool) {
+        self.job_pool = OnceLock::from(job_pool);
+    }
+
     pub fn create_entity(&mut self) -> Entity {
-        let entity = Entity::from_num(self.entities_count);
-        self.entities_count += 1;
-        for component_array in self.component_arrays.values_mut() {
-            component_array.push_none();
-        }
+        self.flush_entities();
+        let entity = self.reserve_entity();
+        self.flush_entities();
         entity
     }
 
+    /// Reserves a handle without mutable access to the world. The entity becomes alive
+    /// on the next flush_entities, which every structural change does first
+    pub fn reserve_entity(&self) -> Entity {
+        let reserved = self.reserved_entities.fetch_add(1, Ordering::Relaxed);
+        let free_count = self.free_entities.len();
+        if reserved < free_count {
+            let index = self.free_entities[free_count - 1 - reserved];
+            Entity::new(index, self.entities[index].generation)
+        } else {
+            Entity::new(self.entities.len() + reserved - free_count, 0)
+        }
+    }
+
+    /// Makes reserved entities alive, in the same order reserve_entity handed them out
+    pub fn flush_entities(&mut self) {
+        let reserved = std::mem::take(self.reserved_entities.get_mut());
+        for _ in 0..reserved {
+            let index = match self.free_entities.pop() {
+                Some(index) => index,
+                None => {
+                    self.entities.push(EntityMeta {
+                        generation: 0,
+                        alive: false,
+                        location: EntityLocation {
+                            archetype: ArchetypeId::EMPTY,
+                            row: 0,
+                        },
+                    });
+                    for component_array in self.component_arrays.values_mut() {
+                        component_array.push_none();
+                    }
+                    self.entities.len() - 1
+                }
+            };
+
+            let entity = Entity::new(index, self.entities[index].generation);
+            let row = self
+                .archetypes
+                .get_mut(ArchetypeId::EMPTY)
+                .push_entity(entity);
+            let meta = &mut self.entities[index];
+            meta.alive = true;
+            meta.location = EntityLocation {
+                archetype: ArchetypeId::EMPTY,
+                row,
+            };
+        }
+    }
+
+    /// Removes all components of the entity and frees its slot for reuse.
+    /// Returns false if the handle is stale.
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        self.flush_entities();
+        if !self.is_alive(entity) {
+            return false;
+        }
+        let location = self.entities[entity.to_num()].location;
+        let archetype = self.archetypes.get(location.archetype);
+        for type_id in archetype.get_types() {
+            self.component_arrays
+                .get_mut(type_id)
+                .unwrap()
+                .clear_entity(entity, location);
+        }
+        self.remove_from_archetype(location);
+
+        let meta = &mut self.entities[entity.to_num()];
+        meta.alive = false;
+        meta.generation = meta.generation.wrapping_add(1);
+        self.free_entities.push(entity.to_num());
+        true
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        match self.entities.get(entity.to_num()) {
+            Some(meta) => meta.alive && meta.generation == entity.get_generation(),
+            None => false,
+        }
+    }
+
+    /// Returns the current handle for the slot, if it is occupied
+    pub fn get_entity(&self, index: usize) -> Option<Entity> {
+        let meta = self.entities.get(index)?;
+        if meta.alive {
+            Some(Entity::new(index, meta.generation))
+        } else {
+            None
+        }
+    }
+
+    pub fn get_entity_location(&self, entity: Entity) -> Option<EntityLocation> {
+        if self.is_alive(entity) {
+            Some(self.entities[entity.to_num()].location)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_entities_capacity(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn get_entities_count(&self) -> usize {
+        self.entities.len() - self.free_entities.len()
+    }
+
+    pub fn get_archetypes(&self) -> &Archetypes {
+        &self.archetypes
+    }
+
     pub fn register_component<T: Component>(&mut self) {
+        self.register_component_with_storage::<T>(self.default_storage);
+    }
+
+    pub fn register_component_with_storage<T: Component>(&mut self, storage_type: StorageType) {
+        self.try_register_component_with_storage::<T>(storage_type)
+            .unwrap_or_else(|error| panic!("{}", error));
+    }
+
+    pub fn try_register_component_with_storage<T: Component>(
+        &mut self,
+        storage_type: StorageType,
+    ) -> Result<(), EcsError> {
         let type_id = T::get_type_id();
-        assert!(
-            self.component_arrays.get(&type_id).is_none(),
-            "Already registered"
-        );
-        let mut component_array = CACell::<T>::new();
-        for _ in 0..self.entities_count {
+        if self.component_arrays.contains_key(&type_id) {
+            return Err(EcsError::ComponentAlreadyRegistered(type_name::<T>()));
+        }
+        let mut component_array = CACell::<T>::new(storage_type);
+        for _ in 0..self.entities.len() {
             component_array.push_none();
         }
         self.component_arrays
             .insert(type_id, Box::new(component_array));
+        Ok(())
+    }
+
+    pub fn is_component_registered<T: Component>(&self) -> bool {
+        self.component_arrays.contains_key(&T::get_type_id())
+    }
+
+    /// In strict mode components must be registered explicitly, inserting or querying
+    /// an unregistered component panics. Useful in debug builds to catch typos:
+    /// world.set_strict_registration(cfg!(debug_assertions))
+    pub fn set_strict_registration(&mut self, strict: bool) {
+        self.strict_registration = strict;
+    }
+
+    pub fn is_strict_registration(&self) -> bool {
+        self.strict_registration
+    }
+
+    /// Registers the component with the default storage, unless in strict mode
+    pub fn ensure_component_registered<T: Component>(&mut self) {
+        self.try_ensure_component_registered::<T>()
+            .unwrap_or_else(|error| panic!("{}", error));
+    }
+
+    pub fn try_ensure_component_registered<T: Component>(&mut self) -> Result<(), EcsError> {
+        if self.is_component_registered::<T>() {
+            return Ok(());
+        }
+        if self.strict_registration {
+            return Err(EcsError::ComponentNotRegistered(type_name::<T>()));
+        }
+        self.try_register_component_with_storage::<T>(self.default_storage)
+    }
+
+    pub fn spawn<B: Bundle>(&mut self, bundle: B) -> Entity {
+        let entity = self.create_entity();
+        self.insert_bundle(entity, bundle);
+        entity
+    }
+
+    pub fn insert_bundle<B: Bundle>(&mut self, entity: Entity, bundle: B) {
+        self.try_insert_bundle(entity, bundle)
+            .unwrap_or_else(|error| panic!("{}", error));
+    }
+
+    /// Moves the entity to the archetype with all components of the bundle in one step.
+    /// Components the entity already has are replaced
+    pub fn try_insert_bundle<B: Bundle>(&mut self, entity: Entity, bundle: B) -> Result<(), EcsError> {
+        self.flush_entities();
+        if !self.is_alive(entity) {
+            return Err(EcsError::DeadEntity(entity));
+        }
+        let mut type_ids = Vec::new();
+        bundle.get_type_ids(self, &mut type_ids)?;
+        let mut unique = type_ids.clone();
+        unique.sort();
+        unique.dedup();
+        if unique.len() != type_ids.len() {
+            return Err(EcsError::DuplicateBundleComponent(type_name::<B>()));
+        }
+
+        let previous = self.entities[entity.to_num()].location.archetype;
+        let target = self.archetypes.get_with_added_all(previous, &type_ids);
+        if target != previous {
+            self.move_entity(entity, target);
+        }
+        bundle.write_components(self, entity, previous);
+        Ok(())
+    }
+
+    /// Registers the component of a bundle and appends its type id, used by Bundle
+    pub fn add_bundle_type_id<T: Component>(
+        &mut self,
+        _component: &T,
+        type_ids: &mut Vec<TypeId>,
+    ) -> Result<(), EcsError> {
+        self.try_ensure_component_registered::<T>()?;
+        type_ids.push(T::get_type_id());
+        Ok(())
+    }
+
+    /// Writes the component of a bundle, used by Bundle. The entity must already be
+    /// in an archetype with the component, previous is its archetype before the insertion
+    pub fn write_bundle_component<T: Component>(
+        &mut self,
+        component: T,
+        entity: Entity,
+        previous: ArchetypeId,
+    ) {
+        let tick = self.get_change_tick();
+        let location = self.entities[entity.to_num()].location;
+        let replace = self.archetypes.get(previous).contains(T::get_type_id());
+        let components = self.get_component_array_mut::<T>().unwrap();
+        if replace {
+            components.replace_component(component, entity, location, tick);
+        } else {
+            components.set_component(component, entity, location, tick);
+        }
     }
 
     pub fn set_component<T: Component>(&mut self, component: T, entity: Entity) {
-        unsafe {
+        self.try_set_component(component, entity)
+            .unwrap_or_else(|error| panic!("{}", error));
+    }
+
+    pub fn try_set_component<T: Component>(
+        &mut self,
+        component: T,
+        entity: Entity,
+    ) -> Result<(), EcsError> {
+        self.flush_entities();
+        if !self.is_alive(entity) {
+            return Err(EcsError::DeadEntity(entity));
+        }
+        self.try_ensure_component_registered::<T>()?;
+        let type_id = T::get_type_id();
+
+        let tick = self.get_change_tick();
+        let location = self.entities[entity.to_num()].location;
+        if self.archetypes.get(location.archetype).contains(type_id) {
             self.get_component_array_mut::<T>()
-                .expect("Component is not registered")
-                .set_component(component, entity)
+                .unwrap()
+                .replace_component(component, entity, location, tick);
+            return Ok(());
         }
+
+        let target = self.archetypes.get_with_added(location.archetype, type_id);
+        let location = self.move_entity(entity, target);
+        self.get_component_array_mut::<T>()
+            .unwrap()
+            .set_component(component, entity, location, tick);
+        Ok(())
     }
 
     pub fn remove_component<T: Component>(&mut self, entity: Entity) {
-        unsafe {
-            self.get_component_array_mut::<T>()
-                .expect("Component is not registered")
-                .remove_component(entity);
+        self.try_remove_component::<T>(entity)
+            .unwrap_or_else(|error| panic!("{}", error));
+    }
+
+    /// Removing a component the entity doesn't have is not an error
+    pub fn try_remove_component<T: Component>(&mut self, entity: Entity) -> Result<(), EcsError> {
+        self.flush_entities();
+        if !self.is_alive(entity) {
+            return Err(EcsError::DeadEntity(entity));
+        }
+        if self.try_get_queried_component_array::<T>()?.is_none() {
+            return Ok(());
         }
+        let type_id = T::get_type_id();
+
+        let location = self.entities[entity.to_num()].location;
+        if !self.archetypes.get(location.archetype).contains(type_id) {
+            return Ok(());
+        }
+        self.get_component_array_mut::<T>()
+            .unwrap()
+            .remove_component(entity, location);
+
+        let target = self
+            .archetypes
+            .get_with_removed(location.archetype, type_id);
+        self.move_entity(entity, target);
+        Ok(())
     }
 
     pub fn get_component_array<T: Component>(&self) -> Option<&CACell<T>> {
@@ -64,6 +382,23 @@ impl World {
             .downcast_ref::<CACell<T>>()
     }
 
+    /// Component array for queries. Missing array means the query is empty,
+    /// in strict mode it panics
+    pub fn get_queried_component_array<T: Component>(&self) -> Option<&CACell<T>> {
+        self.try_get_queried_component_array::<T>()
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_get_queried_component_array<T: Component>(
+        &self,
+    ) -> Result<Option<&CACell<T>>, EcsError> {
+        let component_array = self.get_component_array::<T>();
+        if component_array.is_none() && self.strict_registration {
+            return Err(EcsError::ComponentNotRegistered(type_name::<T>()));
+        }
+        Ok(component_array)
+    }
+
     pub fn get_component_array_mut<T: Component>(&mut self) -> Option<&mut CACell<T>> {
         let type_id = T::get_type_id();
         self.component_arrays
@@ -72,7 +407,184 @@ impl World {
             .downcast_mut::<CACell<T>>()
     }
 
+    /// Replaces the resource if it is already inserted
+    pub fn insert_resource<R: Resource>(&mut self, resource: R) {
+        self.resources.insert(resource);
+    }
+
+    pub fn remove_resource<R: Resource>(&mut self) -> Option<R> {
+        self.resources.remove::<R>()
+    }
+
+    pub fn contains_resource<R: Resource>(&self) -> bool {
+        self.resources.contains::<R>()
+    }
+
+    pub fn resource<R: Resource>(&self) -> ResRef<'_, R> {
+        self.try_resource::<R>()
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn resource_mut<R: Resource>(&self) -> ResRefMut<'_, R> {
+        self.try_resource_mut::<R>()
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_resource<R: Resource>(&self) -> Result<ResRef<'_, R>, EcsError> {
+        self.get_resource_cell::<R>()
+            .ok_or(EcsError::MissingResource(type_name::<R>()))?
+            .try_borrow()
+            .ok_or(EcsError::BorrowConflict(type_name::<R>()))
+    }
+
+    pub fn try_resource_mut<R: Resource>(&self) -> Result<ResRefMut<'_, R>, EcsError> {
+        self.get_resource_cell::<R>()
+            .ok_or(EcsError::MissingResource(type_name::<R>()))?
+            .try_borrow_mut()
+            .ok_or(EcsError::BorrowConflict(type_name::<R>()))
+    }
+
+    pub fn get_resource_cell<R: Resource>(&self) -> Option<&ResourceCell<R>> {
+        self.resources.get_cell::<R>()
+    }
+
+    pub fn get_resource_cell_mut<R: Resource>(&mut self) -> Option<&mut ResourceCell<R>> {
+        self.resources.get_cell_mut::<R>()
+    }
+
+    /// Inserts Events<E> resource, updated by update_events. Does nothing if already added
+    pub fn add_event<E: Event>(&mut self) {
+        if self.contains_resource::<Events<E>>() {
+            return;
+        }
+        self.insert_resource(Events::<E>::new());
+        self.event_updaters.push(|world| {
+            if let Some(events) = world.get_resource_cell_mut::<Events<E>>() {
+                events.get_mut().update();
+            }
+        });
+    }
+
+    /// Panics if the event is not added
+    pub fn send_event<E: Event>(&mut self, event: E) {
+        match self.get_resource_cell_mut::<Events<E>>() {
+            Some(events) => events.get_mut().send(event),
+            None => panic!("Event {} is not added", type_name::<E>()),
+        }
+    }
+
+    /// Swaps buffers of all added events, called by the engine once per frame
+    pub fn update_events(&mut self) {
+        for index in 0..self.event_updaters.len() {
+            let update = self.event_updaters[index];
+            update(self);
+        }
+    }
+
     pub fn query<'w, T: Fetcherable>(&'w self) -> Query<'w, T> {
         Query::<'w, T>::new(self)
     }
+
+    pub fn query_with_ticks<'w, T: Fetcherable>(&'w self, ticks: SystemTicks) -> Query<'w, T> {
+        Query::<'w, T>::new_with_ticks(self, ticks)
+    }
+
+    pub fn try_query<'w, T: Fetcherable>(&'w self) -> Result<Query<'w, T>, EcsError> {
+        Query::<'w, T>::try_new(self)
+    }
+
+    /// Moves components shared by both archetypes. Components missing in the target
+    /// archetype must be removed before the call
+    fn move_entity(&mut self, entity: Entity, target: ArchetypeId) -> EntityLocation {
+        let from = self.entities[entity.to_num()].location;
+        let target_archetype = self.archetypes.get(target);
+        for type_id in self.archetypes.get(from.archetype).get_types() {
+            if target_archetype.contains(*type_id) {
+                self.component_arrays
+                    .get_mut(type_id)
+                    .unwrap()
+                    .move_entity(from, target);
+            }
+        }
+        self.remove_from_archetype(from);
+
+        let row = self.archetypes.get_mut(target).push_entity(entity);
+        let location = EntityLocation {
+            archetype: target,
+            row,
+        };
+        self.entities[entity.to_num()].location = location;
+        location
+    }
+
+    fn remove_from_archetype(&mut self, location: EntityLocation) {
+        let archetype = self.archetypes.get_mut(location.archetype);
+        if let Some(moved) = archetype.swap_remove_entity(location.row) {
+            self.entities[moved.to_num()].location.row = location.row;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ecs::{EcsError, StorageType, World};
+
+    #[derive(PartialEq, Debug)]
+    struct Position(i32);
+    #[derive(PartialEq, Debug)]
+    struct Velocity(i32);
+
+    fn table_world() -> World {
+        let mut world = World::new();
+        world.register_component_with_storage::<Position>(StorageType::Table);
+        world.register_component_with_storage::<Velocity>(StorageType::Table);
+        world
+    }
+
+    #[test]
+    fn table_components_survive_archetype_moves() {
+        let mut world = table_world();
+        let entity = world.spawn((Position(1),));
+        world.set_component(Velocity(2), entity);
+        world.remove_component::<Velocity>(entity);
+        world.set_component(Velocity(3), entity);
+
+        let query = world.query::<(&Position, &Velocity)>();
+        let (position, velocity) = query.try_fetch_entity(entity).unwrap();
+        assert_eq!(*position, Position(1));
+        assert_eq!(*velocity, Velocity(3));
+    }
+
+    #[test]
+    fn despawn_moves_last_row_into_the_hole() {
+        let mut world = table_world();
+        let first = world.spawn((Position(1), Velocity(10)));
+        let second = world.spawn((Position(2), Velocity(20)));
+        let last = world.spawn((Position(3), Velocity(30)));
+        let archetype = world.get_entity_location(first).unwrap().archetype;
+
+        assert!(world.despawn(first));
+
+        let location = world.get_entity_location(last).unwrap();
+        assert_eq!(location.archetype, archetype);
+        assert_eq!(location.row, 0);
+        assert_eq!(world.get_archetypes().get(archetype).get_entities(), &[last, second]);
+        let query = world.query::<(&Position, &Velocity)>();
+        let (position, velocity) = query.try_fetch_entity(last).unwrap();
+        assert_eq!(*position, Position(3));
+        assert_eq!(*velocity, Velocity(30));
+        let (position, _) = query.try_fetch_entity(second).unwrap();
+        assert_eq!(*position, Position(2));
+    }
+
+    #[test]
+    fn bundle_with_duplicate_component_is_rejected() {
+        let mut world = table_world();
+        let entity = world.spawn((Velocity(1),));
+
+        let result = world.try_insert_bundle(entity, (Position(1), Position(2)));
+
+        assert!(matches!(result, Err(EcsError::DuplicateBundleComponent(_))));
+        assert!(world.query::<&Position>().try_fetch_entity(entity).is_err());
+    }
 }
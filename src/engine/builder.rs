@@ -0,0 +1,341 @@
+use crate::ecs::{Event, World};
+use crate::engine::engine_event::{FocusLost, QuitRequested, WindowResized};
+use crate::engine::engine_subsystem::EngineSubsystem;
+use crate::engine::engine_subsystem::Subsystems;
+use crate::engine::error::EngineError;
+use crate::engine::logic::LogicFuncType;
+use crate::engine::logic::{StateLogic, StateObject};
+use crate::engine::plugin::{Plugin, PluginId};
+use crate::engine::state::States;
+use crate::engine::time::{Clock, Time};
+use crate::engine::{Engine, Window};
+use crate::input::{Input, InputEvent};
+use crate::utils;
+use sdl2::video::{FullscreenType, GLProfile, SwapInterval};
+
+type SetupFn = Box<dyn FnOnce(&mut Engine)>;
+
+/// WindowMode
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum WindowMode {
+    Windowed,
+    /// Changes the display mode to the window size
+    Fullscreen,
+    /// Borderless window covering the desktop
+    FullscreenDesktop,
+}
+
+impl From<WindowMode> for FullscreenType {
+    fn from(window_mode: WindowMode) -> Self {
+        match window_mode {
+            WindowMode::Windowed => FullscreenType::Off,
+            WindowMode::Fullscreen => FullscreenType::True,
+            WindowMode::FullscreenDesktop => FullscreenType::Desktop,
+        }
+    }
+}
+
+/// EngineBuilder
+/// Configures the engine before it's created:
+/// let engine = EngineBuilder::new().title("game").size(1280, 720).msaa(4).build()?;
+/// Plugins, logics and subsystems are added through a mutable reference:
+/// let mut builder = EngineBuilder::new().headless(true);
+/// builder.add_plugin(PhysicsPlugin);
+/// let engine = builder.build()?;
+pub struct EngineBuilder {
+    headless: bool,
+    clock: Clock,
+    title: String,
+    width: u32,
+    height: u32,
+    window_mode: WindowMode,
+    resizable: bool,
+    swap_interval: SwapInterval,
+    /// SDL picks the version if None
+    gl_version: Option<(u8, u8)>,
+    gl_profile: Option<GLProfile>,
+    msaa_samples: u8,
+    gl_debug: bool,
+    plugins: Vec<PluginId>,
+    /// First failed add_plugin, returned by build
+    plugin_error: Option<EngineError>,
+    /// Applied to the created engine in the order they were added
+    setup: Vec<SetupFn>,
+}
+
+impl EngineBuilder {
+    pub fn new() -> Self {
+        Self {
+            headless: false,
+            clock: Clock::System,
+            title: "rust engine".to_string(),
+            width: 800,
+            height: 600,
+            window_mode: WindowMode::Windowed,
+            resizable: true,
+            swap_interval: SwapInterval::VSync,
+            gl_version: None,
+            gl_profile: None,
+            msaa_samples: 0,
+            gl_debug: true,
+            plugins: Vec::new(),
+            plugin_error: None,
+            setup: Vec::new(),
+        }
+    }
+
+    /// No SDL video, window or GL context. Input is driven by Input::inject_event,
+    /// quit by Engine::request_quit. Render and Swap functions are skipped
+    pub fn headless(mut self, headless: bool) -> Self {
+        self.headless = headless;
+        self
+    }
+
+    pub fn clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = title.to_string();
+        self
+    }
+
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn window_mode(mut self, window_mode: WindowMode) -> Self {
+        self.window_mode = window_mode;
+        self
+    }
+
+    pub fn resizable(mut self, resizable: bool) -> Self {
+        self.resizable = resizable;
+        self
+    }
+
+    /// VSync by default
+    pub fn swap_interval(mut self, swap_interval: SwapInterval) -> Self {
+        self.swap_interval = swap_interval;
+        self
+    }
+
+    pub fn gl_version(mut self, major: u8, minor: u8) -> Self {
+        self.gl_version = Some((major, minor));
+        self
+    }
+
+    pub fn gl_profile(mut self, profile: GLProfile) -> Self {
+        self.gl_profile = Some(profile);
+        self
+    }
+
+    /// Samples per pixel, 0 disables multisampling
+    pub fn msaa(mut self, samples: u8) -> Self {
+        self.msaa_samples = samples;
+        self
+    }
+
+    /// Debug GL context, on by default for debugging with RenderDoc
+    pub fn gl_debug(mut self, debug: bool) -> Self {
+        self.gl_debug = debug;
+        self
+    }
+
+    /// Builds the plugin right away, so its dependencies must be added before it.
+    /// If the plugin is already added or any of its dependencies is not, the plugin
+    /// is skipped and build returns EngineError::Plugin
+    pub fn add_plugin<P: Plugin>(&mut self, plugin: P) -> &mut Self {
+        if let Err(error) = self.check_plugin(&plugin) {
+            self.plugin_error.get_or_insert(error);
+            return self;
+        }
+        self.plugins.push(PluginId::of::<P>());
+        plugin.build(self);
+        self
+    }
+
+    fn check_plugin<P: Plugin>(&self, plugin: &P) -> Result<(), EngineError> {
+        let id = PluginId::of::<P>();
+        if self.plugins.contains(&id) {
+            return Err(EngineError::Plugin(format!(
+                "Plugin {} is already added",
+                id.name
+            )));
+        }
+        for dependency in plugin.get_dependencies() {
+            if !self.plugins.contains(&dependency) {
+                return Err(EngineError::Plugin(format!(
+                    "Plugin {} depends on {}, which is not added before it",
+                    id.name, dependency.name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn has_plugin<P: Plugin>(&self) -> bool {
+        self.plugins.contains(&PluginId::of::<P>())
+    }
+
+    pub fn add_logic<T: StateObject>(&mut self, logic: StateLogic<T>) -> &mut Self {
+        self.setup
+            .push(Box::new(move |engine| engine.add_logic(logic)));
+        self
+    }
+
+    pub fn add_subsystem<T: EngineSubsystem>(&mut self, subsystem: T) -> &mut Self {
+        self.setup
+            .push(Box::new(move |engine| engine.add_subsystem(subsystem)));
+        self
+    }
+
+    pub fn add_state<S: States>(&mut self, initial: S) -> &mut Self {
+        self.setup
+            .push(Box::new(move |engine| engine.add_state(initial)));
+        self
+    }
+
+    pub fn add_event<E: Event>(&mut self) -> &mut Self {
+        self.setup.push(Box::new(|engine| engine.add_event::<E>()));
+        self
+    }
+
+    /// For registering components, inserting resources and spawning entities
+    pub fn configure_world(&mut self, configure: impl FnOnce(&mut World) + 'static) -> &mut Self {
+        self.setup.push(Box::new(move |engine| {
+            configure(engine.get_subsystem_mut::<World>())
+        }));
+        self
+    }
+
+    pub fn build(self) -> Result<Engine, EngineError> {
+        if let Some(error) = self.plugin_error {
+            return Err(error);
+        }
+
+        #[cfg(feature = "profiler")]
+        let perf = crate::utils::scoped_perf::ScopedPerf::new();
+
+        let window = if self.headless {
+            None
+        } else {
+            Some(self.create_window()?)
+        };
+
+        let mut subsystems = Subsystems::new();
+        subsystems.add(Time::new(self.clock));
+        subsystems.add(Input::new());
+
+        let mut engine = Engine {
+            world: World::new(),
+            exit_flag: false,
+            quit_requested: false,
+            logics: Vec::new(),
+            state_appliers: Vec::new(),
+            screenshot_requests: Vec::new(),
+            frame_stages: vec![
+                LogicFuncType::FixedUpdate,
+                LogicFuncType::Update,
+                LogicFuncType::PostUpdate,
+                LogicFuncType::Render,
+                LogicFuncType::Swap,
+            ],
+            parallel_stages: Vec::new(),
+            schedules: Vec::new(),
+            schedules_dirty: true,
+            window,
+            subsystems,
+        };
+        engine.add_event::<WindowResized>();
+        engine.add_event::<FocusLost>();
+        engine.add_event::<QuitRequested>();
+        engine.add_event::<InputEvent>();
+        for setup in self.setup {
+            setup(&mut engine);
+        }
+        Ok(engine)
+    }
+
+    fn create_window(&self) -> Result<Window, EngineError> {
+        let sdl_context = sdl2::init().map_err(EngineError::PlatformInit)?;
+        let sdl_video = sdl_context.video().map_err(EngineError::PlatformInit)?;
+
+        // Attributes are read when the window and the context are created
+        let gl_attr = sdl_video.gl_attr();
+        if let Some((major, minor)) = self.gl_version {
+            gl_attr.set_context_version(major, minor);
+        }
+        if let Some(profile) = self.gl_profile {
+            gl_attr.set_context_profile(profile);
+        }
+        if self.msaa_samples > 0 {
+            gl_attr.set_multisample_buffers(1);
+            gl_attr.set_multisample_samples(self.msaa_samples);
+        }
+        if self.gl_debug {
+            gl_attr.set_context_flags().debug().set();
+        }
+
+        let mut window_builder = sdl_video.window(&self.title, self.width, self.height);
+        window_builder.opengl().position_centered();
+        if self.resizable {
+            window_builder.resizable();
+        }
+        match self.window_mode {
+            WindowMode::Windowed => {}
+            WindowMode::Fullscreen => {
+                window_builder.fullscreen();
+            }
+            WindowMode::FullscreenDesktop => {
+                window_builder.fullscreen_desktop();
+            }
+        }
+        let sdl_window = window_builder
+            .build()
+            .map_err(|error| EngineError::WindowCreation(error.to_string()))?;
+
+        let gl_context = sdl_window
+            .gl_create_context()
+            .map_err(EngineError::GlContext)?;
+        sdl_video
+            .gl_set_swap_interval(self.swap_interval)
+            .map_err(EngineError::GlContext)?;
+        gl::load_with(|s| sdl_video.gl_get_proc_address(s) as *const std::os::raw::c_void);
+
+        unsafe { gl::ClearColor(0.3, 0.3, 0.5, 1.0) };
+
+        let version = unsafe {
+            let version = gl::GetString(gl::VERSION);
+            if version.is_null() {
+                return Err(EngineError::GlContext(
+                    "GL version is unavailable".to_string(),
+                ));
+            }
+            utils::c_string_to_str(version)
+                .map_err(|error| EngineError::GlContext(error.to_string()))?
+        };
+        println!("VERSION: {}", version);
+
+        let sdl_event_pump = sdl_context
+            .event_pump()
+            .map_err(EngineError::PlatformInit)?;
+
+        Ok(Window {
+            sdl_context,
+            sdl_video,
+            sdl_window,
+            gl_context,
+            sdl_event_pump,
+        })
+    }
+}
+
+impl Default for EngineBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,20 @@
+//! Events published by the engine while polling window events,
+//! read by logic functions through EventReader
+
+/// WindowResized
+/// New size of the window in pixels
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct WindowResized {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// FocusLost
+/// The window lost keyboard focus
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct FocusLost;
+
+/// QuitRequested
+/// The window was closed or the platform asked the application to quit
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct QuitRequested;
@@ -1,6 +1,92 @@
-use crate::engine::Engine;
+use crate::ecs::{Access, World};
+use crate::utils::to_any::ToAny;
+use std::any::{type_name, TypeId};
+use std::collections::HashMap;
 
-pub trait EngineSubsystem {
-    fn get<'a>(engine: &'a Engine) -> &'a Self;
-    fn get_mut<'a>(engine: &'a mut Engine) -> &'a mut Self;
+/// EngineSubsystem
+/// Sync, because logic functions running concurrently share the engine interface.
+/// Hooks are called by the engine outside of logic functions, in the order
+/// the subsystems were added, shutdown in reverse order
+pub trait EngineSubsystem: ToAny + Sync {
+    /// Access of a logic function taking the subsystem as a parameter. Subsystems are
+    /// only changed by the engine between stages, so reading them conflicts with nothing
+    fn update_access(_access: &mut Access)
+    where
+        Self: Sized,
+    {
+    }
+
+    /// Before Init logic functions
+    fn init(&mut self, _world: &mut World) {}
+
+    /// At the start of every frame, before the frame stages
+    fn pre_frame(&mut self, _world: &mut World) {}
+
+    /// After the last frame stage
+    fn post_frame(&mut self, _world: &mut World) {}
+
+    /// After Shutdown logic functions
+    fn shutdown(&mut self, _world: &mut World) {}
+}
+
+/// Subsystems
+/// Type-keyed registry of the engine subsystems
+pub(in crate::engine) struct Subsystems {
+    subsystems: Vec<Box<dyn EngineSubsystem>>,
+    indices: HashMap<TypeId, usize>,
+}
+
+impl Subsystems {
+    pub fn new() -> Self {
+        Self {
+            subsystems: Vec::new(),
+            indices: HashMap::new(),
+        }
+    }
+
+    /// Panics if a subsystem of the type is already added
+    pub fn add<T: EngineSubsystem>(&mut self, subsystem: T) {
+        let type_id = TypeId::of::<T>();
+        assert!(
+            !self.indices.contains_key(&type_id),
+            "Subsystem {} is already added",
+            type_name::<T>()
+        );
+        self.indices.insert(type_id, self.subsystems.len());
+        self.subsystems.push(Box::new(subsystem));
+    }
+
+    pub fn get<T: EngineSubsystem>(&self) -> Option<&T> {
+        let index = *self.indices.get(&TypeId::of::<T>())?;
+        (*self.subsystems[index]).as_any().downcast_ref()
+    }
+
+    pub fn get_mut<T: EngineSubsystem>(&mut self) -> Option<&mut T> {
+        let index = *self.indices.get(&TypeId::of::<T>())?;
+        (*self.subsystems[index]).as_any_mut().downcast_mut()
+    }
+
+    pub fn init(&mut self, world: &mut World) {
+        for subsystem in &mut self.subsystems {
+            subsystem.init(world);
+        }
+    }
+
+    pub fn pre_frame(&mut self, world: &mut World) {
+        for subsystem in &mut self.subsystems {
+            subsystem.pre_frame(world);
+        }
+    }
+
+    pub fn post_frame(&mut self, world: &mut World) {
+        for subsystem in &mut self.subsystems {
+            subsystem.post_frame(world);
+        }
+    }
+
+    pub fn shutdown(&mut self, world: &mut World) {
+        for subsystem in self.subsystems.iter_mut().rev() {
+            subsystem.shutdown(world);
+        }
+    }
 }
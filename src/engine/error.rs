@@ -0,0 +1,51 @@
+use crate::ecs::EcsError;
+use std::error::Error;
+use std::fmt;
+
+/// EngineError
+#[derive(Clone, Debug)]
+pub enum EngineError {
+    /// SDL or its video subsystem failed to initialise
+    PlatformInit(String),
+    WindowCreation(String),
+    /// GL context creation or its setup failed
+    GlContext(String),
+    /// Borrow conflict, missing component or resource, dead entity
+    Ecs(EcsError),
+    /// Plugin added twice or before its dependencies
+    Plugin(String),
+    /// No logic with the name to enable or disable
+    UnknownLogic(String),
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineError::PlatformInit(message) => {
+                write!(f, "Platform initialisation failed: {}", message)
+            }
+            EngineError::WindowCreation(message) => {
+                write!(f, "Window creation failed: {}", message)
+            }
+            EngineError::GlContext(message) => write!(f, "GL context error: {}", message),
+            EngineError::Ecs(error) => write!(f, "ECS error: {}", error),
+            EngineError::Plugin(message) => write!(f, "Plugin error: {}", message),
+            EngineError::UnknownLogic(name) => write!(f, "No logic with the name {}", name),
+        }
+    }
+}
+
+impl Error for EngineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EngineError::Ecs(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<EcsError> for EngineError {
+    fn from(error: EcsError) -> Self {
+        EngineError::Ecs(error)
+    }
+}
@@ -1,110 +1,311 @@
 use crate::ecs::*;
+use crate::engine::run_condition::RunCondition;
+use crate::engine::state::States;
+use crate::engine::system_param::{SystemContext, SystemFunction, SystemParam};
 use crate::engine::{Commands, EngineInterface};
+use std::any::type_name;
+use std::marker::PhantomData;
 
-#[derive(Eq, PartialEq, Copy, Clone)]
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
 pub enum LogicFuncType {
     Init,
+    /// Run at the start of a frame when a state is exited, see StateLogic::add_on_exit
+    OnExit,
+    /// Run at the start of a frame when a state is entered, after OnExit functions
+    OnEnter,
+    /// Run zero or more times per frame with Time::get_fixed_delta step
+    FixedUpdate,
     Update,
     PostUpdate,
     Render,
     Swap,
     Shutdown,
+    /// User stage, added to the frame with Engine::add_stage_before/add_stage_after.
+    /// Sequential unless added as parallel
+    Stage(&'static str),
 }
 
-trait EcsFunction<T: StateObject> {
-    fn call(&self, state: &mut T, world: &World, ei: &EngineInterface, commands: &mut Commands);
+impl LogicFuncType {
+    /// Render and Swap functions use the GL context of the main thread, so only
+    /// state transition, FixedUpdate, Update and PostUpdate functions run concurrently.
+    /// User stages may draw too, whether they are parallel is chosen when they are added
+    pub fn is_parallel(&self) -> bool {
+        matches!(
+            self,
+            LogicFuncType::OnExit
+                | LogicFuncType::OnEnter
+                | LogicFuncType::FixedUpdate
+                | LogicFuncType::Update
+                | LogicFuncType::PostUpdate
+        )
+    }
 }
 
-struct EcsFunctionT<T: StateObject, F: Fetcherable> {
-    func: fn(&mut T, Query<'_, F>, &EngineInterface, &mut Commands),
+trait LogicFunction<T: StateObject>: Send {
+    fn call(&mut self, state: &mut T, world: &World, ei: &EngineInterface, commands: &mut Commands);
+    fn get_access(&self) -> &Access;
 }
 
-impl<T: StateObject, F: Fetcherable> EcsFunction<T> for EcsFunctionT<T, F> {
-    fn call(&self, state: &mut T, world: &World, ei: &EngineInterface, commands: &mut Commands) {
-        let q = world.query::<F>();
-        (self.func)(state, q, ei, commands);
-    }
+struct SystemFunctionT<T: StateObject, Marker, Func: SystemFunction<T, Marker>> {
+    func: Func,
+    param_state: <Func::Param as SystemParam>::State,
+    /// Computed once when the function is added
+    access: Access,
+    /// Change tick of the previous call, used by Added and Changed filters
+    last_run: u32,
+    marker: PhantomData<fn() -> (T, Marker)>,
 }
 
-enum LogicFuncVariant<T: StateObject> {
-    Default(fn(&mut T, &EngineInterface, &mut Commands)),
-    Ecs(Box<dyn EcsFunction<T>>),
+impl<T: StateObject, Marker: 'static, Func: SystemFunction<T, Marker>> LogicFunction<T>
+    for SystemFunctionT<T, Marker, Func>
+{
+    fn call(
+        &mut self,
+        state: &mut T,
+        world: &World,
+        ei: &EngineInterface,
+        commands: &mut Commands,
+    ) {
+        let ticks = SystemTicks {
+            last_run: self.last_run,
+            this_run: world.increment_change_tick(),
+        };
+        let context = SystemContext::new(world, ei, ticks, commands);
+        // SAFETY: Parameters of the function were checked for conflicts when it was added
+        let param = unsafe { Func::Param::get_param(&mut self.param_state, &context) };
+        self.func.run(state, param);
+        self.last_run = ticks.this_run;
+    }
+
+    fn get_access(&self) -> &Access {
+        &self.access
+    }
 }
 
 struct LogicFunc<T: StateObject> {
+    name: &'static str,
     func_type: LogicFuncType,
-    function: LogicFuncVariant<T>,
+    order: LogicFuncOrder,
+    conditions: Vec<RunCondition>,
+    function: Box<dyn LogicFunction<T>>,
+}
+
+/// LogicFuncOrder
+/// Labels of a function and labels of the functions it must run before or after.
+/// Constraints only order functions of the same LogicFuncType
+#[derive(Clone, Default, Debug)]
+pub struct LogicFuncOrder {
+    pub labels: Vec<String>,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+/// LogicFuncConfig
+/// Returned by add_function to set the ordering and run conditions of the function:
+/// logic.add_function(movement, LogicFuncType::Update).label("movement").after("input");
+pub struct LogicFuncConfig<'a> {
+    order: &'a mut LogicFuncOrder,
+    conditions: &'a mut Vec<RunCondition>,
 }
 
-pub trait StateObject: 'static {}
+impl LogicFuncConfig<'_> {
+    /// Several functions can share a label, constraints then apply to all of them
+    pub fn label(self, label: &str) -> Self {
+        self.order.labels.push(label.to_string());
+        self
+    }
+
+    pub fn before(self, label: &str) -> Self {
+        self.order.before.push(label.to_string());
+        self
+    }
+
+    pub fn after(self, label: &str) -> Self {
+        self.order.after.push(label.to_string());
+        self
+    }
+
+    /// The function runs only if all its conditions are true
+    pub fn run_if(self, condition: RunCondition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+}
+
+/// LogicFuncInfo
+/// Description of a logic function used to build schedules
+pub struct LogicFuncInfo {
+    pub name: &'static str,
+    pub func_type: LogicFuncType,
+    pub order: LogicFuncOrder,
+    /// Names of the run conditions
+    pub conditions: Vec<String>,
+    /// Functions taking the engine interface can access the whole world through it,
+    /// so their access is exclusive
+    pub access: Access,
+}
+
+pub trait StateObject: Send + 'static {}
+
+impl<T: Send + 'static> StateObject for T {}
+
+pub trait Logic: Send {
+    fn get_name(&self) -> &'static str;
+
+    /// Functions of a disabled logic are skipped
+    fn is_enabled(&self) -> bool;
+
+    fn set_enabled(&mut self, enabled: bool);
 
-impl<T: 'static> StateObject for T {}
+    /// Functions in the order they were added
+    fn get_functions(&self) -> Vec<LogicFuncInfo>;
 
-pub trait Logic {
-    fn run(
+    /// Checks run conditions of the function
+    fn should_run(&mut self, index: usize, world: &World, ei: &EngineInterface) -> bool;
+
+    fn run_function(
         &mut self,
+        index: usize,
         world: &World,
-        func_type: LogicFuncType,
         ei: &EngineInterface,
         commands: &mut Commands,
     );
 }
 
 pub struct StateLogic<T: StateObject> {
+    name: &'static str,
+    enabled: bool,
     object: T,
     functions: Vec<LogicFunc<T>>,
 }
 
 impl<T: StateObject> StateLogic<T> {
+    /// Named after the state type
     pub fn new(object: T) -> Self {
         Self {
+            name: type_name::<T>(),
+            enabled: true,
             object,
             functions: Vec::new(),
         }
     }
 
-    pub fn add_function(
+    /// Name used by Command::SetLogicEnabled and in the schedule dump
+    pub fn with_name(mut self, name: &'static str) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// Parameters of the function are any SystemParam, e.g. Query, &Time or ResRef.
+    /// Panics if two parameters conflict, like Query<&mut T> and Query<&T>
+    pub fn add_function<Marker: 'static, Func: SystemFunction<T, Marker>>(
         &mut self,
-        function: fn(&mut T, &EngineInterface, &mut Commands),
+        function: Func,
         func_type: LogicFuncType,
-    ) {
+    ) -> LogicFuncConfig<'_> {
+        let mut access = Access::new();
+        Func::Param::update_access(&mut access);
         let lf = LogicFunc {
+            name: type_name::<Func>(),
             func_type,
-            function: LogicFuncVariant::Default(function),
+            order: LogicFuncOrder::default(),
+            conditions: Vec::new(),
+            function: Box::new(SystemFunctionT {
+                func: function,
+                param_state: Func::Param::init_state(),
+                access,
+                last_run: 0,
+                marker: PhantomData,
+            }),
         };
-        self.functions.push(lf);
+        self.push_function(lf)
+    }
+
+    /// Runs when the state is entered, including the initial state on the first frame
+    pub fn add_on_enter<S: States, Marker: 'static, Func: SystemFunction<T, Marker>>(
+        &mut self,
+        state: S,
+        function: Func,
+    ) -> LogicFuncConfig<'_> {
+        self.add_function(function, LogicFuncType::OnEnter)
+            .run_if(RunCondition::on_enter(state))
     }
 
-    pub fn add_ecs_function<F: Fetcherable + 'static>(
+    pub fn add_on_exit<S: States, Marker: 'static, Func: SystemFunction<T, Marker>>(
         &mut self,
-        function: fn(&mut T, query: Query<F>, &EngineInterface, &mut Commands),
+        state: S,
+        function: Func,
+    ) -> LogicFuncConfig<'_> {
+        self.add_function(function, LogicFuncType::OnExit)
+            .run_if(RunCondition::on_exit(state))
+    }
+
+    /// Runs in the given stage while the state is current
+    pub fn add_on_update<S: States, Marker: 'static, Func: SystemFunction<T, Marker>>(
+        &mut self,
+        state: S,
+        function: Func,
         func_type: LogicFuncType,
-    ) {
-        let lf = LogicFunc {
-            func_type,
-            function: LogicFuncVariant::Ecs(Box::new(EcsFunctionT { func: function })),
-        };
-        self.functions.push(lf);
+    ) -> LogicFuncConfig<'_> {
+        self.add_function(function, func_type)
+            .run_if(RunCondition::in_state(state))
+    }
+
+    fn push_function(&mut self, function: LogicFunc<T>) -> LogicFuncConfig<'_> {
+        self.functions.push(function);
+        let function = self.functions.last_mut().unwrap();
+        LogicFuncConfig {
+            order: &mut function.order,
+            conditions: &mut function.conditions,
+        }
     }
 }
 
 impl<T: StateObject> Logic for StateLogic<T> {
-    fn run(
+    fn get_name(&self) -> &'static str {
+        self.name
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn get_functions(&self) -> Vec<LogicFuncInfo> {
+        self.functions
+            .iter()
+            .map(|function| LogicFuncInfo {
+                name: function.name,
+                func_type: function.func_type,
+                order: function.order.clone(),
+                conditions: function
+                    .conditions
+                    .iter()
+                    .map(|condition| condition.get_name().to_string())
+                    .collect(),
+                access: function.function.get_access().clone(),
+            })
+            .collect()
+    }
+
+    fn should_run(&mut self, index: usize, world: &World, ei: &EngineInterface) -> bool {
+        self.functions[index]
+            .conditions
+            .iter_mut()
+            .all(|condition| condition.check(world, ei))
+    }
+
+    fn run_function(
         &mut self,
+        index: usize,
         world: &World,
-        func_type: LogicFuncType,
         ei: &EngineInterface,
         commands: &mut Commands,
     ) {
-        for function in &self.functions {
-            if function.func_type == func_type {
-                match &function.function {
-                    LogicFuncVariant::Default(f) => {
-                        f(&mut self.object, ei, commands);
-                    }
-                    LogicFuncVariant::Ecs(f) => f.call(&mut self.object, &world, ei, commands),
-                }
-            }
-        }
+        let function = &mut self.functions[index].function;
+        function.call(&mut self.object, world, ei, commands);
     }
 }
@@ -1,42 +1,177 @@
-mod engine_subsystem;
+pub mod builder;
+pub mod engine_event;
+pub mod engine_subsystem;
+pub mod error;
 pub mod logic;
+pub mod plugin;
+pub mod run_condition;
+pub mod schedule;
+pub mod state;
+pub mod system_param;
 pub mod time;
 
 extern crate gl;
 
-use crate::ecs::World;
-use crate::engine::engine_subsystem::EngineSubsystem;
+use crate::ecs::{Access, Bundle, Component, Entity, Event, Resource, World};
+use crate::engine::builder::{EngineBuilder, WindowMode};
+use crate::engine::engine_event::{FocusLost, QuitRequested, WindowResized};
+use crate::engine::engine_subsystem::{EngineSubsystem, Subsystems};
+use crate::engine::error::EngineError;
 use crate::engine::logic::{Logic, LogicFuncType, StateLogic, StateObject};
+use crate::engine::schedule::{Schedule, ScheduledFunction};
+use crate::engine::state::{apply_state_change, change_state, State, States};
 use crate::engine::time::Time;
 use crate::input::*;
-use crate::utils;
-use sdl2::event::Event;
-use sdl2::keyboard::{Keycode, Scancode};
-
+use crate::utils::to_any::ToAny;
+use gl::types::GLint;
+use sdl2::event::{Event as SdlEvent, WindowEvent};
+use sdl2::keyboard::Scancode;
+use std::any::type_name;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Command
+/// Queued by logic functions, executed by the engine after the batch of the function
 pub enum Command {
     Exit,
+    /// Cancels the quit requested during this frame, e.g. to ask for confirmation
+    /// after reading QuitRequested
+    CancelQuit,
+    /// Deferred structural change of the world
+    World(Box<dyn FnOnce(&mut World) + Send>),
+    /// Change of a State<S> queued by Commands::set_state, push_state or pop_state.
+    /// Applied at the start of the next frame
+    SetState(Box<dyn FnOnce(&mut World) + Send>),
+    /// Stops the game time, FixedUpdate functions don't run while paused
+    SetPaused(bool),
+    SetTimeScale(f64),
+    /// Enables or disables all logics with the name, ignored if there are none.
+    /// Engine::try_set_logic_enabled returns the error instead
+    SetLogicEnabled {
+        name: String,
+        enabled: bool,
+    },
+    /// Functions of the logic are scheduled from the next stage on
+    AddLogic(Box<dyn Logic>),
+    /// Ignored in headless mode
+    SetWindowMode(WindowMode),
+    /// Ignored in headless mode
+    ResizeWindow {
+        width: u32,
+        height: u32,
+    },
+    /// Saves the frame to a PPM file after Swap functions. Ignored in headless mode
+    Screenshot(PathBuf),
 }
 
-pub struct Commands {
+pub struct Commands<'w> {
     commands: Vec<Command>,
+    world: &'w World,
 }
 
-impl Commands {
-    fn new() -> Self {
+impl<'w> Commands<'w> {
+    fn new(world: &'w World) -> Self {
         Self {
             commands: Vec::new(),
+            world,
         }
     }
 
     pub fn queue_command(&mut self, command: Command) {
         self.commands.push(command);
     }
+
+    pub fn queue_world_command(&mut self, command: impl FnOnce(&mut World) + Send + 'static) {
+        self.commands.push(Command::World(Box::new(command)));
+    }
+
+    /// The entity is alive once the commands are executed
+    pub fn spawn(&mut self) -> Entity {
+        self.world.reserve_entity()
+    }
+
+    pub fn spawn_bundle<B: Bundle>(&mut self, bundle: B) -> Entity {
+        let entity = self.spawn();
+        self.queue_world_command(move |world| world.insert_bundle(entity, bundle));
+        entity
+    }
+
+    pub fn despawn(&mut self, entity: Entity) {
+        self.queue_world_command(move |world| {
+            world.despawn(entity);
+        });
+    }
+
+    /// Ignored if the entity is despawned before the command is executed
+    pub fn insert<T: Component>(&mut self, entity: Entity, component: T) {
+        self.queue_world_command(move |world| {
+            if world.is_alive(entity) {
+                world.set_component(component, entity);
+            }
+        });
+    }
+
+    /// Ignored if the entity is despawned before the command is executed
+    pub fn remove<T: Component>(&mut self, entity: Entity) {
+        self.queue_world_command(move |world| {
+            if world.is_alive(entity) {
+                world.remove_component::<T>(entity);
+            }
+        });
+    }
+
+    pub fn insert_resource<R: Resource>(&mut self, resource: R) {
+        self.queue_world_command(move |world| world.insert_resource(resource));
+    }
+
+    /// Panics when executed if the event is not added
+    pub fn send_event<E: Event>(&mut self, event: E) {
+        self.queue_world_command(move |world| world.send_event(event));
+    }
+
+    pub fn add_logic<T: StateObject>(&mut self, logic: StateLogic<T>) {
+        self.commands.push(Command::AddLogic(Box::new(logic)));
+    }
+
+    /// Replaces the current state
+    pub fn set_state<S: States>(&mut self, state: S) {
+        self.queue_state_change(move |current: &mut State<S>| current.set(state));
+    }
+
+    /// Covers the current state with the given one
+    pub fn push_state<S: States>(&mut self, state: S) {
+        self.queue_state_change(move |current: &mut State<S>| current.push(state));
+    }
+
+    /// Exits the current state and resumes the covered one, ignored if none is covered
+    pub fn pop_state<S: States>(&mut self) {
+        self.queue_state_change(|current: &mut State<S>| current.pop());
+    }
+
+    fn queue_state_change<S: States>(
+        &mut self,
+        change: impl FnOnce(&mut State<S>) + Send + 'static,
+    ) {
+        self.commands.push(Command::SetState(Box::new(move |world| {
+            change_state(world, change)
+        })));
+    }
+
+    fn into_commands(self) -> Vec<Command> {
+        self.commands
+    }
 }
 
 pub struct EngineInterface<'a> {
     engine: &'a Engine,
 }
 
+// SAFETY: The interface only gives access to subsystems, which are Sync
+unsafe impl Sync for EngineInterface<'_> {}
+
 impl<'a> EngineInterface<'a> {
     pub fn new(engine: &'a Engine) -> Self {
         Self { engine }
@@ -47,151 +182,316 @@ impl<'a> EngineInterface<'a> {
     }
 }
 
+/// BatchTask
+/// Function of a batch with the state it needs on a worker thread
+struct BatchTask<'a, 'w> {
+    /// Index of the function in the batch
+    index: usize,
+    logic: &'a mut Box<dyn Logic>,
+    function: usize,
+    commands: Commands<'w>,
+    time: Duration,
+}
+
 pub struct Window {
     sdl_context: sdl2::Sdl,
     sdl_video: sdl2::VideoSubsystem,
     sdl_window: sdl2::video::Window,
     gl_context: sdl2::video::GLContext,
+    /// Here and not in Input, because the pump isn't Sync and Input is a subsystem
+    sdl_event_pump: sdl2::EventPump,
+}
+
+impl Window {
+    /// Reads the back buffer, so it must be called before the swap
+    fn save_screenshot(&self, path: &Path) -> io::Result<()> {
+        let (width, height) = self.sdl_window.drawable_size();
+        let row_size = width as usize * 3;
+        let mut pixels = vec![0u8; row_size * height as usize];
+        unsafe {
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(
+                0,
+                0,
+                width as GLint,
+                height as GLint,
+                gl::RGB,
+                gl::UNSIGNED_BYTE,
+                pixels.as_mut_ptr() as *mut std::os::raw::c_void,
+            );
+        }
+
+        // GL rows go bottom to top
+        let mut file = io::BufWriter::new(File::create(path)?);
+        write!(file, "P6\n{} {}\n255\n", width, height)?;
+        for row in pixels.chunks_exact(row_size).rev() {
+            file.write_all(row)?;
+        }
+        file.flush()
+    }
 }
 
 pub struct Engine {
     world: World,
     exit_flag: bool,
+    /// Set by QuitRequested, the engine exits at the end of the frame unless canceled
+    quit_requested: bool,
     logics: Vec<Box<dyn Logic>>,
-    window: Window,
-    input: Input,
-    time: Time,
+    /// Stages run every frame, in order
+    frame_stages: Vec<LogicFuncType>,
+    /// Names of user stages added as parallel
+    parallel_stages: Vec<&'static str>,
+    /// Apply pending changes of every added State<S>
+    state_appliers: Vec<fn(&mut World)>,
+    /// Saved at the end of the frame
+    screenshot_requests: Vec<PathBuf>,
+    /// Schedule for every stage, rebuilt after logics or stages are added
+    schedules: Vec<Schedule>,
+    schedules_dirty: bool,
+    /// None in headless mode
+    window: Option<Window>,
+    /// Time, Input and user subsystems. World is a field of its own, but can be
+    /// got as a subsystem too
+    subsystems: Subsystems,
 }
 
-impl EngineSubsystem for Input {
-    fn get<'a>(engine: &'a Engine) -> &'a Self {
-        &engine.input
-    }
+/// Updated by the engine from SDL keyboard events
+impl EngineSubsystem for Input {}
 
-    fn get_mut<'a>(engine: &'a mut Engine) -> &'a mut Self {
-        &mut engine.input
+impl EngineSubsystem for World {
+    fn update_access(access: &mut Access) {
+        access.set_exclusive();
     }
 }
 
-impl EngineSubsystem for Time {
-    fn get<'a>(engine: &'a Engine) -> &'a Self {
-        &engine.time
+impl Engine {
+    /// Engine with the default window, panics if it can't be created.
+    /// See EngineBuilder for other settings and headless mode
+    pub fn new() -> Self {
+        Self::try_new().unwrap_or_else(|error| panic!("Failed to create the engine: {}", error))
     }
 
-    fn get_mut<'a>(engine: &'a mut Engine) -> &'a mut Self {
-        &mut engine.time
+    pub fn try_new() -> Result<Self, EngineError> {
+        EngineBuilder::new().build()
     }
-}
 
-impl EngineSubsystem for World {
-    fn get<'a>(engine: &'a Engine) -> &'a Self {
-        &engine.world
+    /// Hooks of the subsystem are called from the next Engine::init on,
+    /// panics if a subsystem of the type is already added
+    pub fn add_subsystem<T: EngineSubsystem>(&mut self, subsystem: T) {
+        self.subsystems.add(subsystem);
     }
 
-    fn get_mut<'a>(engine: &'a mut Engine) -> &'a mut Self {
-        &mut engine.world
+    /// Panics if the subsystem is not added
+    pub fn get_subsystem<T: EngineSubsystem>(&self) -> &T {
+        self.try_get_subsystem()
+            .unwrap_or_else(|| panic!("Subsystem {} is not added", type_name::<T>()))
     }
-}
 
-impl Engine {
-    pub fn new() -> Self {
-        #[cfg(feature = "profiler")]
-        let perf = crate::utils::scoped_perf::ScopedPerf::new();
-
-        let window = {
-            let sdl_context = sdl2::init().unwrap();
-            let sdl_video = sdl_context.video().unwrap();
-            let sdl_window = sdl_video
-                .window("rust engine", 800, 600)
-                .opengl()
-                .resizable()
-                .position_centered()
-                .build()
-                .unwrap();
-            sdl_video.gl_attr().set_context_flags().debug().set(); // for debug with RenderDoc
-            let gl_context = sdl_window.gl_create_context().unwrap();
-            sdl_video.gl_set_swap_interval(1).unwrap(); // vsync on
-            gl::load_with(|s| sdl_video.gl_get_proc_address(s) as *const std::os::raw::c_void);
-
-            unsafe { gl::ClearColor(0.3, 0.3, 0.5, 1.0) };
-
-            unsafe {
-                println!(
-                    "VERSION: {}",
-                    utils::c_string_to_str(gl::GetString(gl::VERSION))
-                );
-            }
+    /// Panics if the subsystem is not added
+    pub fn get_subsystem_mut<T: EngineSubsystem>(&mut self) -> &mut T {
+        self.try_get_subsystem_mut()
+            .unwrap_or_else(|| panic!("Subsystem {} is not added", type_name::<T>()))
+    }
 
-            Window {
-                sdl_context,
-                sdl_video,
-                sdl_window,
-                gl_context,
-            }
-        };
+    pub fn try_get_subsystem<T: EngineSubsystem>(&self) -> Option<&T> {
+        match self.world.as_any().downcast_ref::<T>() {
+            Some(world) => Some(world),
+            None => self.subsystems.get::<T>(),
+        }
+    }
 
-        let input = Input::new(window.sdl_context.event_pump().unwrap());
+    pub fn try_get_subsystem_mut<T: EngineSubsystem>(&mut self) -> Option<&mut T> {
+        match self.world.as_any_mut().downcast_mut::<T>() {
+            Some(world) => Some(world),
+            None => self.subsystems.get_mut::<T>(),
+        }
+    }
 
-        let time = Time::new(window.sdl_context.timer().unwrap());
+    pub fn add_logic<T: StateObject>(&mut self, logic: StateLogic<T>) {
+        self.logics.push(Box::new(logic));
+        self.schedules_dirty = true;
+    }
 
-        Self {
-            world: World::new(),
-            exit_flag: false,
-            logics: Vec::new(),
-            window,
-            input,
-            time,
-        }
+    pub fn set_logic_enabled(&mut self, name: &str, enabled: bool) {
+        self.try_set_logic_enabled(name, enabled)
+            .unwrap_or_else(|error| panic!("{}", error));
     }
 
-    pub fn get_subsystem<T: EngineSubsystem>(&self) -> &T {
-        T::get(self)
+    /// Enables or disables all logics with the name
+    pub fn try_set_logic_enabled(&mut self, name: &str, enabled: bool) -> Result<(), EngineError> {
+        Self::set_logics_enabled(&mut self.logics, name, enabled)
     }
 
-    pub fn get_subsystem_mut<T: EngineSubsystem>(&mut self) -> &mut T {
-        T::get_mut(self)
+    /// Adds Events<E> resource, read and written by EventReader and EventWriter
+    pub fn add_event<E: Event>(&mut self) {
+        self.world.add_event::<E>();
     }
 
-    pub fn add_logic<T: StateObject>(&mut self, logic: StateLogic<T>) {
-        self.logics.push(Box::new(logic));
+    /// Adds State<S> resource. The initial state is entered on the first frame,
+    /// panics if the state is already added
+    pub fn add_state<S: States>(&mut self, initial: S) {
+        assert!(
+            !self.world.contains_resource::<State<S>>(),
+            "State {} is already added",
+            type_name::<S>()
+        );
+        self.world.insert_resource(State::new(initial));
+        self.state_appliers.push(apply_state_change::<S>);
+    }
+
+    /// Adds LogicFuncType::Stage(name) to the frame right before the given stage.
+    /// Functions of a parallel stage run on worker threads without the GL context
+    pub fn add_stage_before(&mut self, stage: LogicFuncType, name: &'static str, parallel: bool) {
+        let index = self.get_frame_stage_index(stage);
+        self.insert_frame_stage(index, name, parallel);
+    }
+
+    /// Adds LogicFuncType::Stage(name) to the frame right after the given stage.
+    /// Functions of a parallel stage run on worker threads without the GL context
+    pub fn add_stage_after(&mut self, stage: LogicFuncType, name: &'static str, parallel: bool) {
+        let index = self.get_frame_stage_index(stage);
+        self.insert_frame_stage(index + 1, name, parallel);
+    }
+
+    pub fn get_frame_stages(&self) -> &[LogicFuncType] {
+        &self.frame_stages
+    }
+
+    /// Human readable batches of every stage, for debugging
+    pub fn dump_schedules(&mut self) -> String {
+        self.update_schedules();
+        self.schedules
+            .iter()
+            .map(|schedule| schedule.to_string())
+            .collect()
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.window.is_none()
+    }
+
+    /// True once an Exit command is executed or a quit request isn't canceled
+    pub fn is_exiting(&self) -> bool {
+        self.exit_flag
+    }
+
+    /// Publishes QuitRequested like closing the window does, e.g. in headless mode
+    pub fn request_quit(&mut self) {
+        self.world.send_event(QuitRequested);
+        self.quit_requested = true;
     }
 
     pub fn run(&mut self) {
         self.init();
         while !self.exit_flag {
-            self.time.update();
-
-            self.poll_events();
-            self.update();
-            self.post_update();
-            self.render();
-            self.swap();
+            self.run_frame();
         }
         self.shutdown();
     }
 
-    fn init(&mut self) {
+    /// Runs Init functions. With run_frame and shutdown lets the caller drive
+    /// the frames, e.g. to inject input and advance the manual clock in between
+    pub fn init(&mut self) {
+        self.subsystems.init(&mut self.world);
         self.run_logic_function(LogicFuncType::Init);
     }
 
-    fn shutdown(&mut self) {
+    /// State changes requested during the previous frame are applied after the events
+    /// are polled, before the frame stages
+    pub fn run_frame(&mut self) {
+        self.subsystems.pre_frame(&mut self.world);
+
+        self.world.update_events();
+        self.poll_events();
+        self.update_states();
+        for i in 0..self.frame_stages.len() {
+            self.run_stage(self.frame_stages[i]);
+        }
+        if std::mem::take(&mut self.quit_requested) {
+            self.exit_flag = true;
+        }
+
+        self.subsystems.post_frame(&mut self.world);
+    }
+
+    pub fn shutdown(&mut self) {
         self.run_logic_function(LogicFuncType::Shutdown);
+        self.subsystems.shutdown(&mut self.world);
     }
 
+    /// Keyboard events go to Input, which publishes them as Events<InputEvent>
+    /// after the update. There are no built-in key bindings
     fn poll_events(&mut self) {
-        // TODO: to input
-        for event in self.input.get_event_pump_mut().poll_iter() {
-            match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
-                    ..
-                } => self.exit_flag = true,
-                _ => {}
+        let input = self.subsystems.get_mut::<Input>().unwrap();
+        if let Some(window) = &mut self.window {
+            for event in window.sdl_event_pump.poll_iter() {
+                match event {
+                    SdlEvent::Quit { .. } => {
+                        self.world.send_event(QuitRequested);
+                        self.quit_requested = true;
+                    }
+                    SdlEvent::KeyDown {
+                        scancode: Some(scancode),
+                        repeat: false,
+                        ..
+                    } => input.inject_event(InputEvent::KeyDown(scancode)),
+                    SdlEvent::KeyUp {
+                        scancode: Some(scancode),
+                        ..
+                    } => input.inject_event(InputEvent::KeyUp(scancode)),
+                    SdlEvent::Window {
+                        win_event: WindowEvent::SizeChanged(width, height),
+                        ..
+                    } => self.world.send_event(WindowResized {
+                        width: width as u32,
+                        height: height as u32,
+                    }),
+                    SdlEvent::Window {
+                        win_event: WindowEvent::FocusLost,
+                        ..
+                    } => {
+                        input.release_all_keys();
+                        self.world.send_event(FocusLost);
+                    }
+                    _ => {}
+                }
             }
         }
 
-        self.input.update();
+        input.update();
+        for event in input.get_events() {
+            self.world.send_event(*event);
+        }
+    }
+
+    /// OnExit functions of all exited states run before OnEnter functions
+    fn update_states(&mut self) {
+        for apply in &self.state_appliers {
+            apply(&mut self.world);
+        }
+        self.run_logic_function(LogicFuncType::OnExit);
+        self.run_logic_function(LogicFuncType::OnEnter);
+    }
+
+    /// Render and Swap functions need the GL context, so they are skipped in headless mode
+    fn run_stage(&mut self, stage: LogicFuncType) {
+        match stage {
+            LogicFuncType::Render | LogicFuncType::Swap if self.is_headless() => {}
+            LogicFuncType::FixedUpdate => self.fixed_update(),
+            LogicFuncType::Update => self.update(),
+            LogicFuncType::PostUpdate => self.post_update(),
+            LogicFuncType::Render => self.render(),
+            LogicFuncType::Swap => self.swap(),
+            _ => self.run_logic_function(stage),
+        }
+    }
+
+    fn fixed_update(&mut self) {
+        let steps = self.get_subsystem_mut::<Time>().take_fixed_steps();
+        for _ in 0..steps {
+            self.get_subsystem_mut::<Time>().advance_fixed_time();
+            self.run_logic_function(LogicFuncType::FixedUpdate);
+        }
     }
 
     fn update(&mut self) {
@@ -212,32 +512,194 @@ impl Engine {
 
     fn swap(&mut self) {
         self.run_logic_function(LogicFuncType::Swap);
-        self.window.sdl_window.gl_swap_window();
+        if let Some(window) = &self.window {
+            for path in self.screenshot_requests.drain(..) {
+                if let Err(error) = window.save_screenshot(&path) {
+                    eprintln!("Failed to save screenshot {}: {}", path.display(), error);
+                }
+            }
+            window.sdl_window.gl_swap_window();
+        }
+    }
+
+    fn update_schedules(&mut self) {
+        if !self.schedules_dirty {
+            return;
+        }
+        let stages = [
+            LogicFuncType::Init,
+            LogicFuncType::OnExit,
+            LogicFuncType::OnEnter,
+        ]
+        .into_iter()
+        .chain(self.frame_stages.iter().copied())
+        .chain(std::iter::once(LogicFuncType::Shutdown));
+        self.schedules = stages
+            .map(|stage| Schedule::build(stage, self.is_stage_parallel(stage), &self.logics))
+            .collect();
+        self.schedules_dirty = false;
+    }
+
+    fn get_frame_stage_index(&self, stage: LogicFuncType) -> usize {
+        self.frame_stages
+            .iter()
+            .position(|s| *s == stage)
+            .unwrap_or_else(|| panic!("{:?} is not a frame stage", stage))
+    }
+
+    fn insert_frame_stage(&mut self, index: usize, name: &'static str, parallel: bool) {
+        let stage = LogicFuncType::Stage(name);
+        assert!(
+            !self.frame_stages.contains(&stage),
+            "Stage {} is already added",
+            name
+        );
+        self.frame_stages.insert(index, stage);
+        if parallel {
+            self.parallel_stages.push(name);
+        }
+        self.schedules_dirty = true;
     }
 
+    fn is_stage_parallel(&self, stage: LogicFuncType) -> bool {
+        match stage {
+            LogicFuncType::Stage(name) => self.parallel_stages.contains(&name),
+            _ => stage.is_parallel(),
+        }
+    }
+
+    /// Commands of a batch are executed after all its functions are done
     fn run_logic_function(&mut self, func_type: LogicFuncType) {
-        // TODO: shit?
-        let mut systems = std::mem::take(&mut self.logics);
-        for system in &mut systems {
-            let mut engine_interface = EngineInterface::new(self);
-            let mut commands = Commands::new();
-            system.run(&self.world, func_type, &engine_interface, &mut commands);
-            self.execute_commands(commands.commands);
+        self.update_schedules();
+        let mut schedules = std::mem::take(&mut self.schedules);
+        let mut logics = std::mem::take(&mut self.logics);
+        let schedule = schedules
+            .iter_mut()
+            .find(|schedule| schedule.get_func_type() == func_type)
+            .unwrap();
+        for batch in schedule.get_batches_mut() {
+            let commands = self.run_batch(&mut logics, batch);
+            self.execute_commands(commands, &mut logics);
+        }
+        self.logics = logics;
+        if !self.schedules_dirty {
+            self.schedules = schedules;
         }
-        self.logics = systems;
     }
 
-    fn execute_commands(&mut self, commands: Vec<Command>) {
+    /// Runs functions of the batch on the job pool, returns their commands in batch order.
+    /// Functions with a false run condition are not dispatched
+    fn run_batch(
+        &self,
+        logics: &mut [Box<dyn Logic>],
+        batch: &mut [ScheduledFunction],
+    ) -> Vec<Command> {
+        let engine_interface = EngineInterface::new(self);
+        let mut logics: Vec<Option<&mut Box<dyn Logic>>> = logics.iter_mut().map(Some).collect();
+        let mut tasks: Vec<Mutex<BatchTask>> = Vec::with_capacity(batch.len());
+        for (index, function) in batch.iter_mut().enumerate() {
+            let logic = logics[function.id.logic]
+                .take()
+                .expect("Logic is scheduled twice in a batch");
+            if !logic.is_enabled()
+                || !logic.should_run(function.id.function, &self.world, &engine_interface)
+            {
+                function.stats.skips += 1;
+                continue;
+            }
+            tasks.push(Mutex::new(BatchTask {
+                index,
+                logic,
+                function: function.id.function,
+                commands: Commands::new(&self.world),
+                time: Duration::ZERO,
+            }));
+        }
+
+        let world = &self.world;
+        world.get_job_pool().for_each_index(tasks.len(), |i| {
+            let mut task = tasks[i].lock().unwrap();
+            let task = &mut *task;
+            let start = Instant::now();
+            task.logic
+                .run_function(task.function, world, &engine_interface, &mut task.commands);
+            task.time = start.elapsed();
+        });
+
+        let mut commands = Vec::new();
+        for task in tasks {
+            let task = task.into_inner().unwrap();
+            let stats = &mut batch[task.index].stats;
+            stats.runs += 1;
+            stats.total_time += task.time;
+            commands.extend(task.commands.into_commands());
+        }
+        commands
+    }
+
+    /// Logics are taken out of the engine while their functions run
+    fn execute_commands(&mut self, commands: Vec<Command>, logics: &mut Vec<Box<dyn Logic>>) {
+        self.world.flush_entities();
         for command in commands {
-            self.execute_command(command);
+            self.execute_command(command, logics);
+        }
+    }
+
+    fn set_logics_enabled(
+        logics: &mut [Box<dyn Logic>],
+        name: &str,
+        enabled: bool,
+    ) -> Result<(), EngineError> {
+        let mut found = false;
+        for logic in logics.iter_mut().filter(|logic| logic.get_name() == name) {
+            logic.set_enabled(enabled);
+            found = true;
+        }
+        if found {
+            Ok(())
+        } else {
+            Err(EngineError::UnknownLogic(name.to_string()))
         }
     }
 
-    fn execute_command(&mut self, command: Command) {
+    fn execute_command(&mut self, command: Command, logics: &mut Vec<Box<dyn Logic>>) {
         match command {
             Command::Exit => {
                 self.exit_flag = true;
             }
+            Command::CancelQuit => self.quit_requested = false,
+            Command::World(command) | Command::SetState(command) => command(&mut self.world),
+            Command::SetPaused(paused) => self.get_subsystem_mut::<Time>().set_paused(paused),
+            Command::SetTimeScale(time_scale) => {
+                self.get_subsystem_mut::<Time>().set_time_scale(time_scale)
+            }
+            Command::SetLogicEnabled { name, enabled } => {
+                // There is no caller to return the error to
+                let _ = Self::set_logics_enabled(logics, &name, enabled);
+            }
+            Command::AddLogic(logic) => {
+                logics.push(logic);
+                self.schedules_dirty = true;
+            }
+            Command::SetWindowMode(window_mode) => {
+                if let Some(window) = &mut self.window {
+                    if let Err(error) = window.sdl_window.set_fullscreen(window_mode.into()) {
+                        eprintln!("Failed to set window mode {:?}: {}", window_mode, error);
+                    }
+                }
+            }
+            Command::ResizeWindow { width, height } => {
+                if let Some(window) = &mut self.window {
+                    if let Err(error) = window.sdl_window.set_size(width, height) {
+                        eprintln!("Failed to resize window to {}x{}: {}", width, height, error);
+                    }
+                }
+            }
+            Command::Screenshot(path) => {
+                if !self.is_headless() {
+                    self.screenshot_requests.push(path);
+                }
+            }
         }
     }
 }
@@ -0,0 +1,31 @@
+use crate::engine::builder::EngineBuilder;
+use std::any::{type_name, TypeId};
+
+/// PluginId
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct PluginId {
+    pub type_id: TypeId,
+    pub name: &'static str,
+}
+
+impl PluginId {
+    pub fn of<P: Plugin>() -> Self {
+        Self {
+            type_id: TypeId::of::<P>(),
+            name: type_name::<P>(),
+        }
+    }
+}
+
+/// Plugin
+/// Packs logics, subsystems and world setup of a module, added with one call:
+/// builder.add_plugin(PhysicsPlugin);
+pub trait Plugin: 'static {
+    /// Called once, when the plugin is added
+    fn build(&self, builder: &mut EngineBuilder);
+
+    /// Plugins which must be added before this one
+    fn get_dependencies(&self) -> Vec<PluginId> {
+        Vec::new()
+    }
+}
@@ -0,0 +1,116 @@
+use crate::ecs::{Resource, World};
+use crate::engine::state::{State, StateTransition, States};
+use crate::engine::time::Time;
+use crate::engine::EngineInterface;
+use crate::input::Input;
+use sdl2::keyboard::Scancode;
+use std::any::type_name;
+
+type CheckFn = Box<dyn FnMut(&World, &EngineInterface) -> bool + Send>;
+
+/// RunCondition
+/// Checked by the engine before a function is dispatched, the function is skipped
+/// if any of its conditions is false
+pub struct RunCondition {
+    name: String,
+    check: CheckFn,
+}
+
+impl RunCondition {
+    pub fn new(
+        name: &str,
+        check: impl FnMut(&World, &EngineInterface) -> bool + Send + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            check: Box::new(check),
+        }
+    }
+
+    /// True on the first check and then once the interval has passed since the last true
+    pub fn every_seconds(interval: f64) -> Self {
+        let mut last_time: Option<f64> = None;
+        Self::new(&format!("every {}s", interval), move |_, ei| {
+            let time = ei.get_subsystem::<Time>().get_time();
+            match last_time {
+                Some(last) if time - last < interval => false,
+                _ => {
+                    last_time = Some(time);
+                    true
+                }
+            }
+        })
+    }
+
+    pub fn resource_exists<R: Resource>() -> Self {
+        Self::new(
+            &format!("resource_exists<{}>", type_name::<R>()),
+            |world, _| world.contains_resource::<R>(),
+        )
+    }
+
+    /// True if the resource is inserted and equals the value
+    pub fn resource_equals<R: Resource + PartialEq>(value: R) -> Self {
+        Self::new(
+            &format!("resource_equals<{}>", type_name::<R>()),
+            move |world, _| match world.get_resource_cell::<R>() {
+                Some(cell) => *cell.borrow() == value,
+                None => false,
+            },
+        )
+    }
+
+    /// True while the state is current, false if State<S> is not added
+    pub fn in_state<S: States>(state: S) -> Self {
+        Self::new(
+            &format!("in_state({:?})", state),
+            move |world, _| match world.get_resource_cell::<State<S>>() {
+                Some(cell) => *cell.borrow().get() == state,
+                None => false,
+            },
+        )
+    }
+
+    /// True on the frame the state is entered
+    pub fn on_enter<S: States>(state: S) -> Self {
+        Self::new(&format!("on_enter({:?})", state), move |world, _| {
+            Self::check_transition(world, |transition: &StateTransition<S>| {
+                transition.entered.contains(&state)
+            })
+        })
+    }
+
+    /// True on the frame the state is exited
+    pub fn on_exit<S: States>(state: S) -> Self {
+        Self::new(&format!("on_exit({:?})", state), move |world, _| {
+            Self::check_transition(world, |transition: &StateTransition<S>| {
+                transition.exited.contains(&state)
+            })
+        })
+    }
+
+    /// True on the frame the key went down
+    pub fn key_pressed(scancode: Scancode) -> Self {
+        Self::new(&format!("key_pressed({:?})", scancode), move |_, ei| {
+            ei.get_subsystem::<Input>().is_key_pressed(scancode)
+        })
+    }
+
+    fn check_transition<S: States>(
+        world: &World,
+        check: impl FnOnce(&StateTransition<S>) -> bool,
+    ) -> bool {
+        match world.get_resource_cell::<State<S>>() {
+            Some(cell) => cell.borrow().get_transition().is_some_and(check),
+            None => false,
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub(in crate::engine) fn check(&mut self, world: &World, ei: &EngineInterface) -> bool {
+        (self.check)(world, ei)
+    }
+}
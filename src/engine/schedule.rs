@@ -0,0 +1,408 @@
+use crate::engine::logic::{Logic, LogicFuncInfo, LogicFuncType};
+use std::fmt;
+use std::time::Duration;
+
+/// FunctionId
+/// Index of the logic in the engine and of the function in the logic
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct FunctionId {
+    pub logic: usize,
+    pub function: usize,
+}
+
+/// FunctionStats
+/// Calls of a scheduled function, shown in the schedule dump
+#[derive(Copy, Clone, Default, Debug)]
+pub struct FunctionStats {
+    pub runs: u64,
+    /// Calls skipped because of run conditions
+    pub skips: u64,
+    pub total_time: Duration,
+}
+
+impl FunctionStats {
+    pub fn get_average_time(&self) -> Duration {
+        if self.runs == 0 {
+            Duration::ZERO
+        } else {
+            self.total_time / self.runs as u32
+        }
+    }
+}
+
+/// ScheduledFunction
+pub struct ScheduledFunction {
+    pub id: FunctionId,
+    pub logic_name: &'static str,
+    pub info: LogicFuncInfo,
+    pub stats: FunctionStats,
+}
+
+impl ScheduledFunction {
+    /// Functions of the same logic share its state, so they always conflict
+    fn conflicts_with(&self, other: &ScheduledFunction) -> bool {
+        self.id.logic == other.id.logic || !self.info.access.is_compatible(&other.info.access)
+    }
+
+    /// Explicit before/after constraint between the functions
+    fn must_run_before(&self, other: &ScheduledFunction) -> bool {
+        let has_label = |function: &ScheduledFunction, labels: &[String]| {
+            labels
+                .iter()
+                .any(|label| function.info.order.labels.contains(label))
+        };
+        has_label(other, &self.info.order.before) || has_label(self, &other.info.order.after)
+    }
+
+    /// First label, or the function name if it has none
+    fn get_display_name(&self) -> &str {
+        match self.info.order.labels.first() {
+            Some(label) => label,
+            None => self.info.name,
+        }
+    }
+}
+
+/// Schedule
+/// Functions of a single LogicFuncType split into batches. Functions of a batch
+/// don't conflict and run concurrently, batches run one after another.
+/// Before/after constraints are kept, of two conflicting functions without
+/// a constraint the one added first runs first
+pub struct Schedule {
+    func_type: LogicFuncType,
+    /// Sequential schedules have a single function per batch
+    parallel: bool,
+    batches: Vec<Vec<ScheduledFunction>>,
+}
+
+impl Schedule {
+    /// Panics if the constraints form a cycle
+    pub fn build(func_type: LogicFuncType, parallel: bool, logics: &[Box<dyn Logic>]) -> Self {
+        let mut functions = Vec::new();
+        for (logic_index, logic) in logics.iter().enumerate() {
+            for (function_index, info) in logic.get_functions().into_iter().enumerate() {
+                if info.func_type == func_type {
+                    functions.push(ScheduledFunction {
+                        id: FunctionId {
+                            logic: logic_index,
+                            function: function_index,
+                        },
+                        logic_name: logic.get_name(),
+                        info,
+                        stats: FunctionStats::default(),
+                    });
+                }
+            }
+        }
+
+        let mut graph = OrderGraph::new(functions.len());
+        for (i, first) in functions.iter().enumerate() {
+            for (j, second) in functions.iter().enumerate() {
+                if i != j && first.must_run_before(second) {
+                    graph.add_edge(i, j);
+                }
+            }
+        }
+        if let Err(cycle) = graph.sort() {
+            let names: Vec<&str> = cycle
+                .iter()
+                .map(|i| functions[*i].get_display_name())
+                .collect();
+            panic!(
+                "Ordering cycle in {:?} functions: {}",
+                func_type,
+                names.join(" -> ")
+            );
+        }
+
+        // Order of functions in a logic goes first, so that it isn't overridden
+        // through functions of other logics
+        let mut reachable = graph.get_reachability();
+        for same_logic in [true, false] {
+            for i in 0..functions.len() {
+                for j in i + 1..functions.len() {
+                    if (functions[i].id.logic == functions[j].id.logic) == same_logic
+                        && functions[i].conflicts_with(&functions[j])
+                        && !reachable[i][j]
+                        && !reachable[j][i]
+                    {
+                        graph.add_edge(i, j);
+                        Self::add_reachability(&mut reachable, i, j);
+                    }
+                }
+            }
+        }
+        let order = graph.sort().unwrap();
+
+        let mut batch_indices = vec![0; functions.len()];
+        let mut batches_count = 0;
+        for (position, &i) in order.iter().enumerate() {
+            batch_indices[i] = if parallel {
+                graph.predecessors[i]
+                    .iter()
+                    .map(|&p| batch_indices[p] + 1)
+                    .max()
+                    .unwrap_or(0)
+            } else {
+                position
+            };
+            batches_count = batches_count.max(batch_indices[i] + 1);
+        }
+
+        let mut batches: Vec<Vec<ScheduledFunction>> =
+            (0..batches_count).map(|_| Vec::new()).collect();
+        for (function, batch_index) in functions.into_iter().zip(batch_indices) {
+            batches[batch_index].push(function);
+        }
+        Self {
+            func_type,
+            parallel,
+            batches,
+        }
+    }
+
+    pub fn get_func_type(&self) -> LogicFuncType {
+        self.func_type
+    }
+
+    pub fn get_batches(&self) -> &[Vec<ScheduledFunction>] {
+        &self.batches
+    }
+
+    pub fn get_batches_mut(&mut self) -> &mut [Vec<ScheduledFunction>] {
+        &mut self.batches
+    }
+
+    /// Updates reachability after adding the edge from -> to
+    fn add_reachability(reachable: &mut [Vec<bool>], from: usize, to: usize) {
+        let targets: Vec<usize> = reachable[to]
+            .iter()
+            .enumerate()
+            .filter(|&(y, &is_reachable)| y == to || is_reachable)
+            .map(|(y, _)| y)
+            .collect();
+        for (x, row) in reachable.iter_mut().enumerate() {
+            if x == from || row[from] {
+                for &y in &targets {
+                    row[y] = true;
+                }
+            }
+        }
+    }
+}
+
+/// OrderGraph
+/// Edge i -> j means function i runs before function j
+struct OrderGraph {
+    successors: Vec<Vec<usize>>,
+    predecessors: Vec<Vec<usize>>,
+}
+
+impl OrderGraph {
+    fn new(count: usize) -> Self {
+        Self {
+            successors: vec![Vec::new(); count],
+            predecessors: vec![Vec::new(); count],
+        }
+    }
+
+    fn add_edge(&mut self, from: usize, to: usize) {
+        if !self.successors[from].contains(&to) {
+            self.successors[from].push(to);
+            self.predecessors[to].push(from);
+        }
+    }
+
+    /// Topological order preferring lower indices, or the nodes of a cycle
+    fn sort(&self) -> Result<Vec<usize>, Vec<usize>> {
+        let count = self.successors.len();
+        let mut in_degree: Vec<usize> = self.predecessors.iter().map(Vec::len).collect();
+        let mut order = Vec::with_capacity(count);
+        while order.len() < count {
+            let Some(next) = (0..count).find(|&i| in_degree[i] == 0) else {
+                return Err(self.find_cycle(&in_degree));
+            };
+            in_degree[next] = usize::MAX;
+            for &successor in &self.successors[next] {
+                in_degree[successor] -= 1;
+            }
+            order.push(next);
+        }
+        Ok(order)
+    }
+
+    /// Every node left unsorted has an unsorted predecessor, so walking
+    /// predecessors from any of them ends up in a cycle
+    fn find_cycle(&self, in_degree: &[usize]) -> Vec<usize> {
+        let is_left = |i: usize| in_degree[i] != 0 && in_degree[i] != usize::MAX;
+        let mut path = vec![(0..in_degree.len()).find(|&i| is_left(i)).unwrap()];
+        loop {
+            let current = *path.last().unwrap();
+            let predecessor = *self.predecessors[current]
+                .iter()
+                .find(|&&p| is_left(p))
+                .unwrap();
+            if let Some(start) = path.iter().position(|&i| i == predecessor) {
+                let mut cycle: Vec<usize> = path[start..].iter().rev().copied().collect();
+                cycle.push(cycle[0]);
+                return cycle;
+            }
+            path.push(predecessor);
+        }
+    }
+
+    /// reachable[i][j] is true if i runs before j
+    fn get_reachability(&self) -> Vec<Vec<bool>> {
+        let count = self.successors.len();
+        let mut reachable = vec![vec![false; count]; count];
+        let order = self.sort().unwrap();
+        for &i in order.iter().rev() {
+            let mut row = vec![false; count];
+            for &successor in &self.successors[i] {
+                row[successor] = true;
+                for (is_reachable, &through_successor) in row.iter_mut().zip(&reachable[successor])
+                {
+                    *is_reachable |= through_successor;
+                }
+            }
+            reachable[i] = row;
+        }
+        reachable
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = if self.parallel {
+            "parallel"
+        } else {
+            "sequential"
+        };
+        writeln!(f, "{:?} ({})", self.func_type, mode)?;
+        for (i, batch) in self.batches.iter().enumerate() {
+            writeln!(f, "  batch {}", i)?;
+            for function in batch {
+                write!(f, "    {} [{}]", function.info.name, function.logic_name)?;
+                let order = &function.info.order;
+                if !order.labels.is_empty() {
+                    write!(f, " labels [{}]", order.labels.join(", "))?;
+                }
+                if !order.after.is_empty() {
+                    write!(f, " after [{}]", order.after.join(", "))?;
+                }
+                if !order.before.is_empty() {
+                    write!(f, " before [{}]", order.before.join(", "))?;
+                }
+                if !function.info.conditions.is_empty() {
+                    write!(f, " if [{}]", function.info.conditions.join(", "))?;
+                }
+                let stats = &function.stats;
+                writeln!(
+                    f,
+                    ": {} (runs {}, skipped {}, avg {:?})",
+                    function.info.access,
+                    stats.runs,
+                    stats.skips,
+                    stats.get_average_time()
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ecs::Query;
+    use crate::engine::logic::{Logic, LogicFuncType, StateLogic};
+    use crate::engine::schedule::Schedule;
+
+    struct Position;
+    struct Velocity;
+
+    fn write_position(_: &mut (), _: Query<&mut Position>) {}
+    fn read_position(_: &mut (), _: Query<&Position>) {}
+    fn read_velocity(_: &mut (), _: Query<&Velocity>) {}
+
+    /// Batch of the function with the label
+    fn get_batch(schedule: &Schedule, label: &str) -> usize {
+        schedule
+            .get_batches()
+            .iter()
+            .position(|batch| {
+                batch
+                    .iter()
+                    .any(|function| function.info.order.labels.iter().any(|l| l == label))
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn conflicting_functions_are_in_separate_batches() {
+        let mut writer = StateLogic::new(());
+        writer
+            .add_function(write_position, LogicFuncType::Update)
+            .label("write");
+        let mut reader = StateLogic::new(());
+        reader
+            .add_function(read_position, LogicFuncType::Update)
+            .label("read");
+        let mut other = StateLogic::new(());
+        other
+            .add_function(read_velocity, LogicFuncType::Update)
+            .label("other");
+        let logics: Vec<Box<dyn Logic>> = vec![Box::new(writer), Box::new(reader), Box::new(other)];
+
+        let schedule = Schedule::build(LogicFuncType::Update, true, &logics);
+
+        assert_eq!(schedule.get_batches().len(), 2);
+        assert_eq!(get_batch(&schedule, "write"), 0);
+        assert_eq!(get_batch(&schedule, "read"), 1);
+        assert_eq!(get_batch(&schedule, "other"), 0);
+    }
+
+    #[test]
+    fn before_and_after_are_respected() {
+        let mut first = StateLogic::new(());
+        first
+            .add_function(read_position, LogicFuncType::Update)
+            .label("last")
+            .after("middle");
+        let mut second = StateLogic::new(());
+        second
+            .add_function(read_velocity, LogicFuncType::Update)
+            .label("middle");
+        let mut third = StateLogic::new(());
+        third
+            .add_function(read_position, LogicFuncType::Update)
+            .label("first")
+            .before("middle");
+        let logics: Vec<Box<dyn Logic>> = vec![Box::new(first), Box::new(second), Box::new(third)];
+
+        let schedule = Schedule::build(LogicFuncType::Update, true, &logics);
+
+        assert_eq!(get_batch(&schedule, "first"), 0);
+        assert_eq!(get_batch(&schedule, "middle"), 1);
+        assert_eq!(get_batch(&schedule, "last"), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Ordering cycle in Update functions: b -> a -> b")]
+    fn cycle_panics_with_labels() {
+        let mut logic = StateLogic::new(());
+        logic
+            .add_function(read_position, LogicFuncType::Update)
+            .label("a")
+            .before("b");
+        logic
+            .add_function(read_velocity, LogicFuncType::Update)
+            .label("b")
+            .before("a");
+        logic
+            .add_function(read_velocity, LogicFuncType::Update)
+            .label("c");
+        let logics: Vec<Box<dyn Logic>> = vec![Box::new(logic)];
+
+        Schedule::build(LogicFuncType::Update, true, &logics);
+    }
+}
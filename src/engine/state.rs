@@ -0,0 +1,219 @@
+use crate::ecs::World;
+use std::any::type_name;
+use std::fmt::Debug;
+
+/// States
+/// Value of an application state, usually an enum like Menu, Loading, Game
+pub trait States: Eq + Clone + Debug + Send + Sync + 'static {}
+
+impl<T: Eq + Clone + Debug + Send + Sync + 'static> States for T {}
+
+/// StateChange
+/// Requested by Commands, applied at the start of the next frame
+#[derive(Clone, Debug)]
+enum StateChange<S: States> {
+    /// Entering the initial state
+    Enter,
+    Set(S),
+    Push(S),
+    Pop,
+}
+
+/// StateTransition
+/// States exited and entered by the changes applied at the start of a frame.
+/// A state entered and exited by the same changes is in neither
+#[derive(Clone, Debug)]
+pub struct StateTransition<S: States> {
+    pub exited: Vec<S>,
+    pub entered: Vec<S>,
+}
+
+impl<S: States> StateTransition<S> {
+    fn exit(&mut self, state: S) {
+        match self.entered.iter().position(|entered| *entered == state) {
+            Some(index) => {
+                self.entered.remove(index);
+            }
+            None => self.exited.push(state),
+        }
+    }
+
+    fn enter(&mut self, state: S) {
+        match self.exited.iter().position(|exited| *exited == state) {
+            Some(index) => {
+                self.exited.remove(index);
+            }
+            None => self.entered.push(state),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.exited.is_empty() && self.entered.is_empty()
+    }
+}
+
+/// State
+/// Resource with the current state and the stack of states covered by pushed ones.
+/// Pushed state covers the current one without exiting it, popping exits the top
+/// state and resumes the one below without entering it again
+pub struct State<S: States> {
+    current: S,
+    stack: Vec<S>,
+    /// Applied in order at the start of the next frame
+    pending: Vec<StateChange<S>>,
+    transition: Option<StateTransition<S>>,
+}
+
+impl<S: States> State<S> {
+    /// The initial state is entered on the first frame
+    pub(in crate::engine) fn new(initial: S) -> Self {
+        Self {
+            current: initial,
+            stack: Vec::new(),
+            pending: vec![StateChange::Enter],
+            transition: None,
+        }
+    }
+
+    pub fn get(&self) -> &S {
+        &self.current
+    }
+
+    /// States covered by pushed ones, the last one is right below the current
+    pub fn get_stack(&self) -> &[S] {
+        &self.stack
+    }
+
+    /// Transition applied at the start of this frame, if any
+    pub fn get_transition(&self) -> Option<&StateTransition<S>> {
+        self.transition.as_ref()
+    }
+
+    pub(in crate::engine) fn set(&mut self, state: S) {
+        self.pending.push(StateChange::Set(state));
+    }
+
+    pub(in crate::engine) fn push(&mut self, state: S) {
+        self.pending.push(StateChange::Push(state));
+    }
+
+    pub(in crate::engine) fn pop(&mut self) {
+        self.pending.push(StateChange::Pop);
+    }
+
+    /// Applies the pending changes in order, setting the transition of this frame.
+    /// Setting the current state again is not a transition, popping the empty
+    /// stack is ignored
+    fn apply(&mut self) {
+        let mut transition = StateTransition {
+            exited: Vec::new(),
+            entered: Vec::new(),
+        };
+        for change in std::mem::take(&mut self.pending) {
+            match change {
+                StateChange::Enter => transition.enter(self.current.clone()),
+                StateChange::Set(state) if state == self.current => {}
+                StateChange::Set(state) => {
+                    let exited = std::mem::replace(&mut self.current, state.clone());
+                    transition.exit(exited);
+                    transition.enter(state);
+                }
+                StateChange::Push(state) => {
+                    let covered = std::mem::replace(&mut self.current, state.clone());
+                    self.stack.push(covered);
+                    transition.enter(state);
+                }
+                StateChange::Pop => {
+                    if let Some(resumed) = self.stack.pop() {
+                        let exited = std::mem::replace(&mut self.current, resumed);
+                        transition.exit(exited);
+                    }
+                }
+            }
+        }
+        self.transition = (!transition.is_empty()).then_some(transition);
+    }
+}
+
+/// Applies the pending change of State<S>, registered for every state by Engine::add_state
+pub(in crate::engine) fn apply_state_change<S: States>(world: &mut World) {
+    if let Some(state) = world.get_resource_cell_mut::<State<S>>() {
+        state.get_mut().apply();
+    }
+}
+
+/// Panics if State<S> is not added
+pub(in crate::engine) fn change_state<S: States>(
+    world: &mut World,
+    change: impl FnOnce(&mut State<S>),
+) {
+    match world.get_resource_cell_mut::<State<S>>() {
+        Some(state) => change(state.get_mut()),
+        None => panic!("State {} is not added", type_name::<State<S>>()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Eq, PartialEq, Clone, Debug)]
+    enum GameState {
+        Menu,
+        Game,
+        Pause,
+    }
+
+    fn get_entered(state: &State<GameState>) -> Vec<GameState> {
+        state
+            .get_transition()
+            .map(|transition| transition.entered.clone())
+            .unwrap_or_default()
+    }
+
+    fn get_exited(state: &State<GameState>) -> Vec<GameState> {
+        state
+            .get_transition()
+            .map(|transition| transition.exited.clone())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn push_before_first_frame_enters_initial_state() {
+        let mut state = State::new(GameState::Menu);
+        state.push(GameState::Pause);
+        state.apply();
+
+        assert_eq!(*state.get(), GameState::Pause);
+        assert_eq!(state.get_stack(), &[GameState::Menu]);
+        assert_eq!(get_entered(&state), vec![GameState::Menu, GameState::Pause]);
+        assert!(get_exited(&state).is_empty());
+    }
+
+    #[test]
+    fn push_and_set_in_one_frame_are_both_applied() {
+        let mut state = State::new(GameState::Menu);
+        state.apply();
+
+        state.push(GameState::Pause);
+        state.set(GameState::Game);
+        state.apply();
+
+        assert_eq!(*state.get(), GameState::Game);
+        assert_eq!(state.get_stack(), &[GameState::Menu]);
+        assert_eq!(get_entered(&state), vec![GameState::Game]);
+        assert!(get_exited(&state).is_empty());
+    }
+
+    #[test]
+    fn pop_of_empty_stack_is_ignored() {
+        let mut state = State::new(GameState::Menu);
+        state.apply();
+
+        state.pop();
+        state.apply();
+
+        assert_eq!(*state.get(), GameState::Menu);
+        assert!(state.get_transition().is_none());
+    }
+}
@@ -0,0 +1,442 @@
+use crate::ecs::{
+    Access, Event, EventReader, EventWriter, Events, Fetcherable, Query, ResRef, ResRefMut,
+    Resource, ResourceCell, SystemTicks, World,
+};
+use crate::engine::engine_subsystem::EngineSubsystem;
+use crate::engine::logic::StateObject;
+use crate::engine::{Commands, EngineInterface};
+use std::any::type_name;
+use std::ops::{Deref, DerefMut};
+
+/// SystemContext
+/// Everything parameters of a single logic function call are fetched from
+pub struct SystemContext<'w> {
+    world: &'w World,
+    ei: &'w EngineInterface<'w>,
+    ticks: SystemTicks,
+    commands: *mut Commands<'w>,
+}
+
+impl<'w> SystemContext<'w> {
+    pub(in crate::engine) fn new(
+        world: &'w World,
+        ei: &'w EngineInterface<'w>,
+        ticks: SystemTicks,
+        commands: &'w mut Commands<'_>,
+    ) -> Self {
+        Self {
+            world,
+            ei,
+            ticks,
+            commands: (commands as *mut Commands).cast(),
+        }
+    }
+}
+
+/// SystemParam
+/// Parameter of a logic function. State is kept between calls of the function,
+/// Item is what the function receives
+pub trait SystemParam {
+    type State: Send + 'static;
+    type Item<'w, 's>;
+
+    fn init_state() -> Self::State;
+
+    /// Adds what the parameter borrows, used for scheduling and conflict detection
+    fn update_access(access: &mut Access);
+
+    /// SAFETY: Parameters fetched for one call must not conflict
+    unsafe fn get_param<'w, 's>(
+        state: &'s mut Self::State,
+        context: &SystemContext<'w>,
+    ) -> Self::Item<'w, 's>;
+}
+
+pub type SystemParamItem<'w, 's, P> = <P as SystemParam>::Item<'w, 's>;
+
+impl<F: Fetcherable + 'static> SystemParam for Query<'_, F> {
+    type State = ();
+    type Item<'w, 's> = Query<'w, F>;
+
+    fn init_state() -> Self::State {}
+
+    fn update_access(access: &mut Access) {
+        F::update_access(access);
+    }
+
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut Self::State,
+        context: &SystemContext<'w>,
+    ) -> Self::Item<'w, 's> {
+        context.world.query_with_ticks::<F>(context.ticks)
+    }
+}
+
+impl SystemParam for &mut Commands<'_> {
+    type State = ();
+    type Item<'w, 's> = &'w mut Commands<'w>;
+
+    fn init_state() -> Self::State {}
+
+    fn update_access(access: &mut Access) {
+        access.add_private_write::<Commands>();
+    }
+
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut Self::State,
+        context: &SystemContext<'w>,
+    ) -> Self::Item<'w, 's> {
+        // SAFETY: Commands can't be taken twice by one function, the buffer outlives the call
+        unsafe { &mut *context.commands }
+    }
+}
+
+/// The whole world is reachable through the interface, so the access is exclusive
+impl SystemParam for &EngineInterface<'_> {
+    type State = ();
+    type Item<'w, 's> = &'w EngineInterface<'w>;
+
+    fn init_state() -> Self::State {}
+
+    fn update_access(access: &mut Access) {
+        access.set_exclusive();
+    }
+
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut Self::State,
+        context: &SystemContext<'w>,
+    ) -> Self::Item<'w, 's> {
+        context.ei
+    }
+}
+
+/// Time, Input or any other subsystem
+impl<S: EngineSubsystem + 'static> SystemParam for &S {
+    type State = ();
+    type Item<'w, 's> = &'w S;
+
+    fn init_state() -> Self::State {}
+
+    fn update_access(access: &mut Access) {
+        S::update_access(access);
+    }
+
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut Self::State,
+        context: &SystemContext<'w>,
+    ) -> Self::Item<'w, 's> {
+        context.ei.get_subsystem::<S>()
+    }
+}
+
+/// Panics if the resource is not inserted
+impl<R: Resource> SystemParam for ResRef<'_, R> {
+    type State = ();
+    type Item<'w, 's> = ResRef<'w, R>;
+
+    fn init_state() -> Self::State {}
+
+    fn update_access(access: &mut Access) {
+        access.add_resource_read::<R>();
+    }
+
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut Self::State,
+        context: &SystemContext<'w>,
+    ) -> Self::Item<'w, 's> {
+        context.world.resource::<R>()
+    }
+}
+
+/// Panics if the resource is not inserted
+impl<R: Resource> SystemParam for ResRefMut<'_, R> {
+    type State = ();
+    type Item<'w, 's> = ResRefMut<'w, R>;
+
+    fn init_state() -> Self::State {}
+
+    fn update_access(access: &mut Access) {
+        access.add_resource_write::<R>();
+    }
+
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut Self::State,
+        context: &SystemContext<'w>,
+    ) -> Self::Item<'w, 's> {
+        context.world.resource_mut::<R>()
+    }
+}
+
+/// None if the resource is not inserted
+impl<R: Resource> SystemParam for Option<ResRef<'_, R>> {
+    type State = ();
+    type Item<'w, 's> = Option<ResRef<'w, R>>;
+
+    fn init_state() -> Self::State {}
+
+    fn update_access(access: &mut Access) {
+        access.add_resource_read::<R>();
+    }
+
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut Self::State,
+        context: &SystemContext<'w>,
+    ) -> Self::Item<'w, 's> {
+        context
+            .world
+            .get_resource_cell::<R>()
+            .map(|cell| cell.borrow())
+    }
+}
+
+/// None if the resource is not inserted
+impl<R: Resource> SystemParam for Option<ResRefMut<'_, R>> {
+    type State = ();
+    type Item<'w, 's> = Option<ResRefMut<'w, R>>;
+
+    fn init_state() -> Self::State {}
+
+    fn update_access(access: &mut Access) {
+        access.add_resource_write::<R>();
+    }
+
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut Self::State,
+        context: &SystemContext<'w>,
+    ) -> Self::Item<'w, 's> {
+        context
+            .world
+            .get_resource_cell::<R>()
+            .map(|cell| cell.borrow_mut())
+    }
+}
+
+/// Local
+/// Value owned by a single function, kept between its calls
+pub struct Local<'s, L>(&'s mut L);
+
+impl<L> Deref for Local<'_, L> {
+    type Target = L;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl<L> DerefMut for Local<'_, L> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0
+    }
+}
+
+impl<L: Default + Send + 'static> SystemParam for Local<'_, L> {
+    type State = L;
+    type Item<'w, 's> = Local<'s, L>;
+
+    fn init_state() -> Self::State {
+        L::default()
+    }
+
+    fn update_access(_access: &mut Access) {}
+
+    unsafe fn get_param<'w, 's>(
+        state: &'s mut Self::State,
+        _context: &SystemContext<'w>,
+    ) -> Self::Item<'w, 's> {
+        Local(state)
+    }
+}
+
+/// Cursor of the reader is kept between calls of the function, the first call
+/// reads all events that haven't expired. Panics if the event is not added
+impl<E: Event> SystemParam for EventReader<'_, '_, E> {
+    type State = usize;
+    type Item<'w, 's> = EventReader<'w, 's, E>;
+
+    fn init_state() -> Self::State {
+        0
+    }
+
+    fn update_access(access: &mut Access) {
+        access.add_resource_read::<Events<E>>();
+    }
+
+    unsafe fn get_param<'w, 's>(
+        state: &'s mut Self::State,
+        context: &SystemContext<'w>,
+    ) -> Self::Item<'w, 's> {
+        EventReader::new(get_events_cell::<E>(context.world).borrow(), state)
+    }
+}
+
+/// Panics if the event is not added
+impl<E: Event> SystemParam for EventWriter<'_, E> {
+    type State = ();
+    type Item<'w, 's> = EventWriter<'w, E>;
+
+    fn init_state() -> Self::State {}
+
+    fn update_access(access: &mut Access) {
+        access.add_resource_write::<Events<E>>();
+    }
+
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut Self::State,
+        context: &SystemContext<'w>,
+    ) -> Self::Item<'w, 's> {
+        EventWriter::new(get_events_cell::<E>(context.world).borrow_mut())
+    }
+}
+
+fn get_events_cell<E: Event>(world: &World) -> &ResourceCell<Events<E>> {
+    world
+        .get_resource_cell::<Events<E>>()
+        .unwrap_or_else(|| panic!("Event {} is not added", type_name::<E>()))
+}
+
+impl SystemParam for () {
+    type State = ();
+    type Item<'w, 's> = ();
+
+    fn init_state() -> Self::State {}
+
+    fn update_access(_access: &mut Access) {}
+
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut Self::State,
+        _context: &SystemContext<'w>,
+    ) -> Self::Item<'w, 's> {
+    }
+}
+
+/// SystemParam for tuple
+/// Panics if two members conflict, e.g. Query<&mut T> and Query<&T>
+macro_rules! impl_system_param_for_tuple {
+    ($($type_name: ident, $var_name: ident, $num: tt),*) => {
+        impl<$($type_name: SystemParam),*> SystemParam for ($($type_name,)*) {
+            type State = ($($type_name::State,)*);
+            type Item<'w, 's> = ($($type_name::Item<'w, 's>,)*);
+
+            fn init_state() -> Self::State {
+                ($($type_name::init_state(),)*)
+            }
+
+            fn update_access(access: &mut Access) {
+                let accesses = [$({
+                    let mut $var_name = Access::new();
+                    $type_name::update_access(&mut $var_name);
+                    ($var_name, type_name::<$type_name>())
+                },)*];
+                for (i, (first, first_name)) in accesses.iter().enumerate() {
+                    for (second, second_name) in &accesses[i + 1..] {
+                        let conflicts: Vec<&str> = first
+                            .get_conflicts(second)
+                            .iter()
+                            .map(|item| item.name)
+                            .collect();
+                        assert!(
+                            conflicts.is_empty(),
+                            "Parameters {} and {} conflict on {}",
+                            first_name,
+                            second_name,
+                            conflicts.join(", ")
+                        );
+                    }
+                }
+                for (member, _) in &accesses {
+                    access.extend(member);
+                }
+            }
+
+            unsafe fn get_param<'w, 's>(
+                state: &'s mut Self::State,
+                context: &SystemContext<'w>,
+            ) -> Self::Item<'w, 's> {
+                unsafe { ($($type_name::get_param(&mut state.$num, context),)*) }
+            }
+        }
+    };
+}
+
+all_tuples!(impl_system_param_for_tuple);
+
+/// SystemFunction
+/// Function or closure taking the logic state and any SystemParam parameters:
+/// fn movement(state: &mut T, query: Query<(&mut Pos, &Vel)>, time: &Time)
+pub trait SystemFunction<T: StateObject, Marker>: Send + 'static {
+    type Param: SystemParam;
+
+    fn run(&self, state: &mut T, param: SystemParamItem<'_, '_, Self::Param>);
+}
+
+impl<T: StateObject, Func> SystemFunction<T, fn()> for Func
+where
+    Func: Fn(&mut T) + Send + 'static,
+{
+    type Param = ();
+
+    fn run(&self, state: &mut T, _param: SystemParamItem<'_, '_, Self::Param>) {
+        self(state);
+    }
+}
+
+/// SystemFunction for functions of 1 to 8 parameters. The double Fn bound lets the
+/// parameter types be inferred from the signature and called with any lifetime
+macro_rules! impl_system_function {
+    ($($type_name: ident, $var_name: ident, $num: tt),*) => {
+        impl<T: StateObject, Func, $($type_name: SystemParam),*>
+            SystemFunction<T, fn($($type_name,)*)> for Func
+        where
+            Func: Fn(&mut T, $($type_name),*)
+                + Fn(&mut T, $(SystemParamItem<$type_name>),*)
+                + Send
+                + 'static,
+        {
+            type Param = ($($type_name,)*);
+
+            fn run(&self, state: &mut T, param: SystemParamItem<'_, '_, Self::Param>) {
+                #[allow(clippy::too_many_arguments)]
+                fn call_inner<T, $($type_name),*>(
+                    func: impl Fn(&mut T, $($type_name),*),
+                    state: &mut T,
+                    $($var_name: $type_name),*
+                ) {
+                    func(state, $($var_name),*)
+                }
+                let ($($var_name,)*) = param;
+                call_inner(self, state, $($var_name),*)
+            }
+        }
+    };
+}
+
+all_tuples!(impl_system_function);
+
+#[cfg(test)]
+mod tests {
+    use crate::ecs::{Query, World};
+    use crate::engine::logic::{LogicFuncType, StateLogic};
+    use crate::engine::{Commands, EngineInterface};
+
+    struct Position(f64);
+
+    #[test]
+    #[should_panic(expected = "conflict on exclusive access")]
+    fn engine_interface_with_query_panics_on_registration() {
+        fn update(_: &mut (), _: &EngineInterface, _: Query<&mut Position>) {}
+        StateLogic::new(()).add_function(update, LogicFuncType::Update);
+    }
+
+    #[test]
+    #[should_panic(expected = "conflict on exclusive access")]
+    fn world_with_query_panics_on_registration() {
+        fn update(_: &mut (), _: &World, _: Query<&Position>) {}
+        StateLogic::new(()).add_function(update, LogicFuncType::Update);
+    }
+
+    #[test]
+    fn engine_interface_with_commands_registers() {
+        fn update(_: &mut (), _: &EngineInterface, _: &mut Commands) {}
+        StateLogic::new(()).add_function(update, LogicFuncType::Update);
+    }
+}
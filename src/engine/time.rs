@@ -1,43 +1,115 @@
+use crate::ecs::World;
+use crate::engine::engine_subsystem::EngineSubsystem;
+use std::time::Instant;
+
+/// Clock
+/// Source of the frame time
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Clock {
+    System,
+    /// Time only moves by Time::advance, for tests and simulations
+    Manual,
+}
+
 pub struct Time {
-    sdl_timer: sdl2::TimerSubsystem,
-    old_perf_counter: u64,
-    perf_counter: u64,
-    perf_freq: f64,
+    clock: Clock,
+    /// Instant instead of the SDL timer, which isn't Sync and can't be in a subsystem
+    start: Instant,
+    /// Time passed by Time::advance with the manual clock
+    manual_time: f64,
     cur_time: f64,
     delta_ms: f64,
     delta_s: f64,
+    real_delta_s: f64,
     fps: f64,
+    paused: bool,
+    time_scale: f64,
+    fixed_delta: f64,
+    /// Spiral of death clamp, time of skipped steps is dropped
+    max_fixed_steps: u32,
+    fixed_accumulator: f64,
+    fixed_time: f64,
+    fixed_alpha: f64,
 }
 
 impl Time {
-    pub(in crate::engine) fn new(sdl_timer: sdl2::TimerSubsystem) -> Self {
-        let perf_freq = sdl_timer.performance_frequency() as f64;
+    pub(in crate::engine) fn new(clock: Clock) -> Self {
+        let start = Instant::now();
         Self {
-            sdl_timer,
-            old_perf_counter: 0,
-            perf_counter: 0,
-            perf_freq,
+            clock,
+            start,
+            manual_time: 0f64,
             cur_time: 0f64,
             delta_ms: 0f64,
             delta_s: 0f64,
+            real_delta_s: 0f64,
             fps: f64::INFINITY,
+            paused: false,
+            time_scale: 1f64,
+            fixed_delta: 1f64 / 60f64,
+            max_fixed_steps: 5,
+            fixed_accumulator: 0f64,
+            fixed_time: 0f64,
+            fixed_alpha: 0f64,
         }
     }
 
     pub(in crate::engine) fn update(&mut self) {
-        self.old_perf_counter = self.perf_counter;
-        self.perf_counter = self.sdl_timer.performance_counter();
-        self.cur_time = self.perf_counter as f64 / self.perf_freq;
-        self.delta_ms =
-            ((self.perf_counter - self.old_perf_counter) * 1000) as f64 / self.perf_freq;
-        self.delta_s = self.delta_ms / 1000f64;
-        self.fps = 1f64 / self.delta_s;
+        let time = match self.clock {
+            Clock::System => self.start.elapsed().as_secs_f64(),
+            Clock::Manual => self.manual_time,
+        };
+        self.real_delta_s = time - self.cur_time;
+        self.cur_time = time;
+        self.delta_s = if self.paused {
+            0f64
+        } else {
+            self.real_delta_s * self.time_scale
+        };
+        self.delta_ms = self.delta_s * 1000f64;
+        self.fps = 1f64 / self.real_delta_s;
+    }
+
+    /// Adds the frame delta to the accumulator and returns the number of fixed steps
+    /// to run this frame
+    pub(in crate::engine) fn take_fixed_steps(&mut self) -> u32 {
+        self.fixed_accumulator += self.delta_s;
+        let mut steps = (self.fixed_accumulator / self.fixed_delta) as u32;
+        if steps > self.max_fixed_steps {
+            steps = self.max_fixed_steps;
+            self.fixed_accumulator =
+                steps as f64 * self.fixed_delta + self.fixed_accumulator % self.fixed_delta;
+        }
+        self.fixed_accumulator -= steps as f64 * self.fixed_delta;
+        self.fixed_alpha = self.fixed_accumulator / self.fixed_delta;
+        steps
+    }
+
+    pub(in crate::engine) fn advance_fixed_time(&mut self) {
+        self.fixed_time += self.fixed_delta;
+    }
+
+    pub fn get_clock(&self) -> Clock {
+        self.clock
     }
 
+    /// Moves the manual clock, the next frame gets the passed time as its delta
+    pub fn advance(&mut self, seconds: f64) {
+        assert_eq!(
+            self.clock,
+            Clock::Manual,
+            "Only the manual clock can be advanced"
+        );
+        assert!(seconds >= 0f64, "Time can't go back");
+        self.manual_time += seconds;
+    }
+
+    /// Real time since the engine start, not affected by pause and time scale
     pub fn get_time(&self) -> f64 {
         self.cur_time
     }
 
+    /// Scaled frame delta, 0 while paused. FixedUpdate steps are taken from it
     pub fn get_delta(&self) -> f64 {
         self.delta_s
     }
@@ -46,7 +118,65 @@ impl Time {
         self.delta_ms
     }
 
+    pub fn get_real_delta(&self) -> f64 {
+        self.real_delta_s
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Takes effect from the next frame, set by Command::SetPaused
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn get_time_scale(&self) -> f64 {
+        self.time_scale
+    }
+
+    /// Takes effect from the next frame, set by Command::SetTimeScale
+    pub fn set_time_scale(&mut self, time_scale: f64) {
+        assert!(time_scale >= 0f64, "Time scale can't be negative");
+        self.time_scale = time_scale;
+    }
+
     pub fn get_fps(&self) -> f64 {
         self.fps
     }
+
+    pub fn get_fixed_delta(&self) -> f64 {
+        self.fixed_delta
+    }
+
+    /// Rate of FixedUpdate functions is 1 / delta
+    pub fn set_fixed_delta(&mut self, delta: f64) {
+        assert!(delta > 0f64, "Fixed delta must be positive");
+        self.fixed_delta = delta;
+    }
+
+    pub fn get_max_fixed_steps(&self) -> u32 {
+        self.max_fixed_steps
+    }
+
+    pub fn set_max_fixed_steps(&mut self, steps: u32) {
+        self.max_fixed_steps = steps;
+    }
+
+    /// Time simulated by FixedUpdate functions, including the running step
+    pub fn get_fixed_time(&self) -> f64 {
+        self.fixed_time
+    }
+
+    /// Part of a fixed step accumulated after the last step, in [0, 1).
+    /// Used to interpolate between the two last fixed states when rendering
+    pub fn get_fixed_alpha(&self) -> f64 {
+        self.fixed_alpha
+    }
+}
+
+impl EngineSubsystem for Time {
+    fn pre_frame(&mut self, _world: &mut World) {
+        self.update();
+    }
 }
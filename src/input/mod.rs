@@ -2,32 +2,72 @@ use sdl2::keyboard::Scancode;
 
 const NUM_KEYS: usize = Scancode::Num as usize;
 
+/// InputEvent
+/// Keyboard event forwarded from SDL or injected, e.g. in headless mode.
+/// Published by the engine as Events<InputEvent> every frame
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum InputEvent {
+    KeyDown(Scancode),
+    KeyUp(Scancode),
+}
+
 pub struct Input {
-    sdl_event_pump: sdl2::EventPump,
     old_keys_states: [bool; NUM_KEYS],
     new_keys_states: [bool; NUM_KEYS],
+    pending_events: Vec<InputEvent>,
+    /// Events applied by the last update
+    events: Vec<InputEvent>,
 }
 
 impl Input {
-    pub fn new(sdl_event_pump: sdl2::EventPump) -> Self {
+    pub fn new() -> Self {
         Self {
-            sdl_event_pump,
             old_keys_states: [false; NUM_KEYS],
             new_keys_states: [false; NUM_KEYS],
+            pending_events: Vec::new(),
+            events: Vec::new(),
         }
     }
 
+    /// Applies events queued since the previous update in order.
+    /// Keys keep their state until an event changes it
     pub fn update(&mut self) {
-        std::mem::swap(&mut self.old_keys_states, &mut self.new_keys_states);
+        self.old_keys_states = self.new_keys_states;
+
+        self.events.clear();
+        std::mem::swap(&mut self.events, &mut self.pending_events);
+        for event in &self.events {
+            match *event {
+                InputEvent::KeyDown(scancode) => self.new_keys_states[scancode as usize] = true,
+                InputEvent::KeyUp(scancode) => self.new_keys_states[scancode as usize] = false,
+            }
+        }
+    }
+
+    /// Applied in the next update. The engine injects SDL keyboard events the same way
+    pub fn inject_event(&mut self, event: InputEvent) {
+        self.pending_events.push(event);
+    }
 
-        let states = sdl2::keyboard::KeyboardState::new(&self.sdl_event_pump);
-        for state in states.scancodes() {
-            debug_assert!((state.0 as usize) < self.new_keys_states.len());
-            // SAFETY: We have enough length for all scancodes
-            unsafe {
-                *self.new_keys_states.get_unchecked_mut(state.0 as usize) = state.1;
+    /// Queues KeyUp for every key that is down after the queued events. SDL may not
+    /// send KeyUp for keys released while the window is out of focus
+    pub fn release_all_keys(&mut self) {
+        let mut keys_states = self.new_keys_states;
+        for event in &self.pending_events {
+            match *event {
+                InputEvent::KeyDown(scancode) => keys_states[scancode as usize] = true,
+                InputEvent::KeyUp(scancode) => keys_states[scancode as usize] = false,
             }
         }
+        for (index, _) in keys_states.iter().enumerate().filter(|(_, down)| **down) {
+            if let Some(scancode) = Scancode::from_i32(index as i32) {
+                self.pending_events.push(InputEvent::KeyUp(scancode));
+            }
+        }
+    }
+
+    pub fn get_events(&self) -> &[InputEvent] {
+        &self.events
     }
 
     pub fn is_key_down(&self, scancode: Scancode) -> bool {
@@ -53,12 +93,4 @@ impl Input {
             !*self.new_keys_states.get_unchecked(idx) && *self.old_keys_states.get_unchecked(idx)
         }
     }
-
-    pub fn get_event_pump(&self) -> &sdl2::EventPump {
-        &self.sdl_event_pump
-    }
-
-    pub fn get_event_pump_mut(&mut self) -> &mut sdl2::EventPump {
-        &mut self.sdl_event_pump
-    }
 }
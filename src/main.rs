@@ -2,6 +2,7 @@ extern crate glm;
 extern crate num;
 extern crate sdl2;
 
+#[macro_use]
 mod ecs;
 mod engine;
 mod input;
@@ -11,12 +12,13 @@ mod utils;
 use gl::SCISSOR_TEST;
 use gl::types::{GLfloat, GLint, GLuint};
 use crate::ecs::*;
+use crate::engine::builder::EngineBuilder;
 use crate::engine::logic::*;
+use crate::engine::plugin::Plugin;
+use crate::engine::run_condition::RunCondition;
 use crate::engine::time::Time;
 use crate::engine::*;
-use crate::input::Input;
 use crate::math::*;
-use crate::num::*;
 use glm::{clamp, cos, DVec3, GenNum, sin, Vec3};
 use sdl2::keyboard::Scancode;
 
@@ -29,6 +31,13 @@ struct Mass(f64);
 #[derive(Clone, Copy, Debug)]
 struct Velocity(DVec3);
 
+#[derive(Bundle)]
+struct PhysBundle {
+    position: Position,
+    mass: Mass,
+    velocity: Velocity,
+}
+
 struct GravitySystemState {
     gravity_constant: f64,
 }
@@ -47,81 +56,86 @@ fn update_gravity_sys(
 fn update_ecs_gravity_sys(
     state: &mut GravitySystemState,
     mut query: Query<(&mut Position, &mut Velocity, &Mass)>,
-    ei: &EngineInterface,
-    commands: &mut Commands,
+    time: &Time,
 ) {
-    for attractable in query.iter() {
-        let mut sum_force = DVec3::zero();
-        for attractor in query.iter() {
-            if attractor.ent == attractable.ent {
-                continue;
-            }
-
-            let to_attractor = attractor.comp.0 .0 - attractable.comp.0 .0;
-            let distance = to_attractor.length();
-            // TODO: shit! glm huita!
-            let tmp = state.gravity_constant * attractor.comp.2 .0 * attractable.comp.2 .0
-                / (distance * distance * distance);
-            let force = to_attractor.map(|v| v * tmp);
-            // TODO: glm is shit!
-            sum_force = sum_force.zip(force, |v1, v2| v1 + v2);
-        }
-        // TODO!
+    let dt = time.get_fixed_delta();
+
+    let mut pairs = query.iter_combinations_mut::<2>();
+    while let Some([first, second]) = pairs.fetch_next() {
+        let (first_pos, mut first_vel, first_mass) = first.comp;
+        let (second_pos, mut second_vel, second_mass) = second.comp;
+
+        let to_second = second_pos.0 - first_pos.0;
+        let distance = to_second.length();
+        // TODO: shit! glm huita!
+        let tmp = state.gravity_constant * dt / (distance * distance * distance);
+        let first_dv = to_second.map(|v| v * tmp * second_mass.0);
+        let second_dv = to_second.map(|v| -v * tmp * first_mass.0);
+        // TODO: glm is shit!
+        first_vel.0 = first_vel.0.zip(first_dv, |v1, v2| v1 + v2);
+        second_vel.0 = second_vel.0.zip(second_dv, |v1, v2| v1 + v2);
     }
-}
-
-fn main() {
-    let mut engine = Engine::new();
 
-    let mut world = engine.get_subsystem_mut::<World>();
-    world.register_component::<Position>();
-    world.register_component::<Mass>();
-    world.register_component::<Velocity>();
+    query.par_for_each_mut(|item| {
+        let (mut pos, vel, _) = item.comp;
+        pos.0 = pos.0.zip(vel.0, |p, v| p + v * dt);
+    });
+}
 
-    {
-        fn create_phys_entity(world: &mut World, pos: DVec3, mass: f64, vel: DVec3) {
-            let e = world.create_entity();
-            world.set_component(Position(pos), e);
-            world.set_component(Mass(mass), e);
-            world.set_component(Velocity(vel), e);
-        }
+struct GravityPlugin;
+
+impl Plugin for GravityPlugin {
+    fn build(&self, builder: &mut EngineBuilder) {
+        builder.configure_world(|world| {
+            world.spawn(PhysBundle {
+                position: Position(DVec3::new(0.0, 0.0, 0.0)),
+                mass: Mass(1e10),
+                velocity: Velocity(DVec3::new(0.0, 0.0, 0.0)),
+            });
+            world.spawn(PhysBundle {
+                position: Position(DVec3::new(1e6, 0.0, 0.0)),
+                mass: Mass(1e5),
+                velocity: Velocity(DVec3::new(0.0, 1e6, 0.0)),
+            });
+            world.spawn(PhysBundle {
+                position: Position(DVec3::new(0.0, 1e6, 0.0)),
+                mass: Mass(1e7),
+                velocity: Velocity(DVec3::new(1e6, 0.0, 0.0)),
+            });
+        });
 
-        create_phys_entity(
-            world,
-            DVec3::new(0.0, 0.0, 0.0),
-            1e10,
-            DVec3::new(0.0, 0.0, 0.0),
-        );
-        create_phys_entity(
-            world,
-            DVec3::new(1e6, 0.0, 0.0),
-            1e5,
-            DVec3::new(0.0, 1e6, 0.0),
-        );
-        create_phys_entity(
-            world,
-            DVec3::new(0.0, 1e6, 0.0),
-            1e7,
-            DVec3::new(1e6, 0.0, 0.0),
-        );
+        let gravity_state = GravitySystemState {
+            gravity_constant: 6.6743e-11,
+        };
+        let mut gravity_logic = StateLogic::new(gravity_state);
+        gravity_logic.add_function(init_gravity_sys, LogicFuncType::Init);
+        gravity_logic
+            .add_function(update_gravity_sys, LogicFuncType::Update)
+            .label("gravity");
+        gravity_logic.add_function(update_ecs_gravity_sys, LogicFuncType::FixedUpdate);
+        builder.add_logic(gravity_logic);
     }
+}
+
+fn main() {
+    let mut builder = EngineBuilder::new();
+    builder.add_plugin(GravityPlugin);
+    let mut engine = builder
+        .build()
+        .unwrap_or_else(|error| panic!("Failed to create the engine: {}", error));
 
     {
         let mut basic_logic = StateLogic::new(0f64);
-        fn update(last_fps_print_time: &mut f64, ei: &EngineInterface, commands: &mut Commands) {
+        fn exit(_: &mut f64, _: &EngineInterface, commands: &mut Commands) {
+            commands.queue_command(Command::Exit);
+        }
+        fn print_fps(last_fps_print_time: &mut f64, ei: &EngineInterface, _: &mut Commands) {
             let time = ei.get_subsystem::<Time>();
-            let input = ei.get_subsystem::<Input>();
-
-            if input.is_key_pressed(Scancode::Escape) {
-                commands.queue_command(Command::Exit);
-            }
-            if time.get_time() - *last_fps_print_time > 1.0 {
-                *last_fps_print_time = time.get_time();
-                println!("FPS: {}", time.get_fps());
-            }
-            else {
-                *last_fps_print_time -= 0.001;
-            }
+            *last_fps_print_time = time.get_time();
+            println!("FPS: {}", time.get_fps());
+        }
+        fn update(last_fps_print_time: &mut f64, _: &EngineInterface, _: &mut Commands) {
+            *last_fps_print_time -= 0.001;
         }
         fn render(last_fps_print_time: &mut f64, ei: &EngineInterface, commands: &mut Commands) {
             unsafe {
@@ -149,21 +163,17 @@ fn main() {
                                sin(*last_fps_print_time as GLfloat * 15002.0) / 2.0 + 0.5, 1.0);
             }
         }
+        basic_logic
+            .add_function(exit, LogicFuncType::Update)
+            .run_if(RunCondition::key_pressed(Scancode::Escape));
+        basic_logic
+            .add_function(print_fps, LogicFuncType::Update)
+            .after("gravity")
+            .run_if(RunCondition::every_seconds(1.0));
         basic_logic.add_function(update, LogicFuncType::Update);
         basic_logic.add_function(render, LogicFuncType::Render);
         engine.add_logic(basic_logic);
     }
 
-    {
-        let gravity_state = GravitySystemState {
-            gravity_constant: 6.6743e-11,
-        };
-        let mut gravity_logic = StateLogic::new(gravity_state);
-        gravity_logic.add_function(init_gravity_sys, LogicFuncType::Init);
-        gravity_logic.add_function(update_gravity_sys, LogicFuncType::Update);
-        gravity_logic.add_ecs_function(update_ecs_gravity_sys, LogicFuncType::Update);
-        engine.add_logic(gravity_logic);
-    }
-
     engine.run();
 }
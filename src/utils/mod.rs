@@ -1,8 +1,10 @@
 pub mod scoped_perf;
 pub mod to_any;
 
-// SAFETY: You must guarantee lifetime for string. Pointer must be c string in UTF-8 or ASCII encoding
-pub unsafe fn c_string_to_str<T: num::Num>(string: *const T) -> &'static str {
+// SAFETY: You must guarantee lifetime for string. Pointer must be a non-null c string
+pub unsafe fn c_string_to_str<T: num::Num>(
+    string: *const T,
+) -> Result<&'static str, std::str::Utf8Error> {
     let mut cur = string as *const u8;
     let size = unsafe {
         while (*cur != 0u8) {
@@ -11,5 +13,5 @@ pub unsafe fn c_string_to_str<T: num::Num>(string: *const T) -> &'static str {
         cur.offset_from(string as *const u8) as usize
     };
     let byte_array = std::slice::from_raw_parts(string as *const u8, size);
-    std::str::from_utf8(byte_array).unwrap()
+    std::str::from_utf8(byte_array)
 }